@@ -1,11 +1,10 @@
 extern crate rqc_core;
 
-use rqc_core::{Arbitrary, BufferOpError, FiniteByteBuffer, Rqc, RqcBuild, TestResult};
-use std::env;
+use rqc_core::{BufferOpError, FiniteByteBuffer, TestResult};
 
-fn check(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
-    let lhs: u8 = Arbitrary::arbitrary(buf)?;
-    let rhs: u8 = Arbitrary::arbitrary(buf)?;
+fn mul_is_add(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
+    let lhs: u8 = buf.generate("lhs")?;
+    let rhs: u8 = buf.generate("rhs")?;
 
     let mul = lhs * rhs;
     let mut add_mul = 0;
@@ -19,13 +18,14 @@ fn check(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
     Ok(TestResult::Passed)
 }
 
-fn main() {
-    let mut args = env::args();
-    let _ = args.next().unwrap();
-    let shm_path = args
-        .next()
-        .expect("must have a path to shm for communication with server");
+fn add_commutes(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
+    let lhs: u8 = buf.generate("lhs")?;
+    let rhs: u8 = buf.generate("rhs")?;
 
-    let rqc: Rqc = RqcBuild::new().build();
-    rqc.run(&shm_path, check)
+    if lhs.wrapping_add(rhs) != rhs.wrapping_add(lhs) {
+        return Ok(TestResult::Failed);
+    }
+    Ok(TestResult::Passed)
 }
+
+rqc_core::main!(mul_is_add, add_commutes);
@@ -4,12 +4,11 @@ extern crate rqc_core;
 #[global_allocator]
 static ALLOC: bh_alloc::fuzz::BumpAlloc = bh_alloc::fuzz::BumpAlloc::INIT;
 
-use rqc_core::{Arbitrary, BufferOpError, FiniteByteBuffer, Rqc, RqcBuild, TestResult};
-use std::env;
+use rqc_core::{BufferOpError, FiniteByteBuffer, TestResult};
 
-fn check(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
-    let s: String = Arbitrary::arbitrary(buf)?;
-    let repeats: u8 = Arbitrary::arbitrary(buf)?;
+fn str_repeat(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
+    let s: String = buf.generate("s")?;
+    let repeats: u8 = buf.generate("repeats")?;
     let repeats: usize = repeats as usize;
 
     if let Some(rpt_len) = s.len().checked_mul(repeats) {
@@ -18,16 +17,7 @@ fn check(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
             return Ok(TestResult::Failed);
         }
     }
-    return Ok(TestResult::Passed);
+    Ok(TestResult::Passed)
 }
 
-fn main() {
-    let mut args = env::args();
-    let _ = args.next().unwrap();
-    let shm_path = args
-        .next()
-        .expect("must have a path to shm for communication with server");
-
-    let rqc: Rqc = RqcBuild::new().build();
-    rqc.run(&shm_path, check)
-}
+rqc_core::main!(str_repeat);
@@ -4,8 +4,9 @@
 use std::borrow::{Cow, ToOwned};
 use std::cell::{Cell, RefCell, UnsafeCell};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+use std::convert::TryFrom;
 use std::ffi::{CString, OsString};
-use std::iter;
+use std::iter::{self, FromIterator};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize};
@@ -38,18 +39,160 @@ pub trait Unstructured {
 pub trait Arbitrary: Sized + 'static {
     /// Generate arbitrary structured data from unstructured data.
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error>;
+
+    /// Generate ever so slightly simpler values than `self`, simplest first.
+    ///
+    /// The default is to not shrink at all.
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(iter::empty())
+    }
+
+    /// A copy of `self`, if one can be made.
+    ///
+    /// Shrinking a compound value builds smaller copies of it, so a value
+    /// holding something that can't be copied isn't shrunk. The default is
+    /// to not copy at all, types that are `Clone` should return a clone.
+    fn try_clone(&self) -> Option<Self> {
+        None
+    }
+}
+
+/// Copies of `elements`, if every one of them can be copied
+fn try_clone_all<A: Arbitrary>(elements: &[A]) -> Option<Vec<A>> {
+    elements.iter().map(Arbitrary::try_clone).collect()
+}
+
+/// Shrink a sequence by removing ever smaller runs of elements, then by
+/// shrinking each element in turn while keeping the others
+fn shrink_elements<A: Arbitrary>(elements: Vec<A>) -> Box<dyn Iterator<Item = Vec<A>>> {
+    let len = elements.len();
+    let elements = Rc::new(elements);
+    let sizes = iter::successors(Some(len), |&size| Some(size / 2)).take_while(|&size| size > 0);
+    let removals = {
+        let elements = Rc::clone(&elements);
+        sizes.flat_map(move |size| {
+            let elements = Rc::clone(&elements);
+            (0..len).step_by(size).filter_map(move |start| {
+                let mut shrunk = try_clone_all(&elements)?;
+                shrunk.drain(start..::std::cmp::min(start + size, len));
+                Some(shrunk)
+            })
+        })
+    };
+    Box::new(removals.chain(shrink_each(elements)))
+}
+
+/// Shrink each element of a sequence in turn, keeping the others as they are
+fn shrink_each<A: Arbitrary>(elements: Rc<Vec<A>>) -> impl Iterator<Item = Vec<A>> {
+    (0..elements.len()).flat_map(move |idx| {
+        let elements = Rc::clone(&elements);
+        elements[idx].shrink().filter_map(move |element| {
+            let mut shrunk = try_clone_all(&elements)?;
+            shrunk[idx] = element;
+            Some(shrunk)
+        })
+    })
+}
+
+/// Shrink a collection as the sequence of its elements, provided they could
+/// be copied out of it
+fn shrink_collection<A, C>(elements: Option<Vec<A>>) -> Box<dyn Iterator<Item = C>>
+where
+    A: Arbitrary,
+    C: FromIterator<A> + 'static,
+{
+    match elements {
+        Some(elements) => Box::new(shrink_elements(elements).map(C::from_iter)),
+        None => Box::new(iter::empty()),
+    }
+}
+
+// Integers shrink toward zero: zero first, then halving the distance from
+// self each time.
+macro_rules! shrink_unsigned {
+    ($x: expr) => {{
+        let x = $x;
+        if x == 0 {
+            return Box::new(iter::empty());
+        }
+        let mut delta = x / 2;
+        Box::new(iter::once(0).chain(iter::from_fn(move || {
+            if delta == 0 {
+                return None;
+            }
+            let candidate = x - delta;
+            delta /= 2;
+            Some(candidate)
+        })))
+    }};
+}
+
+// As shrink_unsigned, trying the absolute value of a negative number second
+macro_rules! shrink_signed {
+    ($x: expr) => {{
+        let x = $x;
+        if x == 0 {
+            return Box::new(iter::empty());
+        }
+        let abs = if x < 0 { x.checked_neg() } else { None };
+        let mut delta = x / 2;
+        Box::new(iter::once(0).chain(abs).chain(iter::from_fn(move || {
+            if delta == 0 {
+                return None;
+            }
+            let candidate = x - delta;
+            delta /= 2;
+            Some(candidate)
+        })))
+    }};
+}
+
+// Floats shrink to zero, then to their integral part and absolute value
+macro_rules! shrink_float {
+    ($x: expr) => {{
+        let x = $x;
+        if x == 0.0 {
+            return Box::new(iter::empty());
+        }
+        let mut candidates = vec![0.0];
+        if x.is_finite() {
+            if x.trunc() != x && x.trunc() != 0.0 {
+                candidates.push(x.trunc());
+            }
+            if x < 0.0 {
+                candidates.push(-x);
+            }
+        }
+        Box::new(candidates.into_iter())
+    }};
 }
 
 impl Arbitrary for () {
     fn arbitrary<U: Unstructured + ?Sized>(_: &mut U) -> Result<Self, U::Error> {
         Ok(())
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(())
+    }
 }
 
 impl Arbitrary for bool {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(<u8 as Arbitrary>::arbitrary(u)? & 1 == 1)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        if *self {
+            Box::new(iter::once(false))
+        } else {
+            Box::new(iter::empty())
+        }
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for u8 {
@@ -58,12 +201,28 @@ impl Arbitrary for u8 {
         u.fill_buffer(&mut x)?;
         Ok(x[0])
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_unsigned!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for i8 {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(<u8 as Arbitrary>::arbitrary(u)? as Self)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_signed!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for u16 {
@@ -72,12 +231,28 @@ impl Arbitrary for u16 {
         u.fill_buffer(&mut x)?;
         Ok(Self::from(x[0]) | Self::from(x[1]) << 8)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_unsigned!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for i16 {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(<u16 as Arbitrary>::arbitrary(u)? as Self)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_signed!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for u32 {
@@ -89,12 +264,28 @@ impl Arbitrary for u32 {
             | Self::from(x[2]) << 16
             | Self::from(x[3]) << 24)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_unsigned!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for i32 {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(<u32 as Arbitrary>::arbitrary(u)? as Self)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_signed!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for u64 {
@@ -110,12 +301,28 @@ impl Arbitrary for u64 {
             | Self::from(x[6]) << 48
             | Self::from(x[7]) << 56)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_unsigned!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for i64 {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(<u64 as Arbitrary>::arbitrary(u)? as Self)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_signed!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for usize {
@@ -127,24 +334,56 @@ impl Arbitrary for usize {
             _ => unreachable!(), // welcome, 128 bit machine users
         })
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_unsigned!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for isize {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(<usize as Arbitrary>::arbitrary(u)? as Self)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_signed!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for f32 {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(Self::from_bits(<u32 as Arbitrary>::arbitrary(u)?))
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_float!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for f64 {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Ok(Self::from_bits(<u64 as Arbitrary>::arbitrary(u)?))
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_float!(*self)
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for char {
@@ -165,6 +404,14 @@ impl Arbitrary for char {
             }
         }
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new((*self as u32).shrink().filter_map(::std::char::from_u32))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl Arbitrary for AtomicBool {
@@ -192,6 +439,10 @@ impl Arbitrary for Duration {
             <u32 as Arbitrary>::arbitrary(u)? % 1_000_000_000,
         ))
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for Option<A> {
@@ -202,6 +453,20 @@ impl<A: Arbitrary> Arbitrary for Option<A> {
             None
         })
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match *self {
+            Some(ref a) => Box::new(iter::once(None).chain(a.shrink().map(Some))),
+            None => Box::new(iter::empty()),
+        }
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        match *self {
+            Some(ref a) => a.try_clone().map(Some),
+            None => Some(None),
+        }
+    }
 }
 
 impl<A: Arbitrary, B: Arbitrary> Arbitrary for Result<A, B> {
@@ -212,6 +477,20 @@ impl<A: Arbitrary, B: Arbitrary> Arbitrary for Result<A, B> {
             Err(Arbitrary::arbitrary(u)?)
         })
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match *self {
+            Ok(ref a) => Box::new(a.shrink().map(Ok)),
+            Err(ref b) => Box::new(b.shrink().map(Err)),
+        }
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        match *self {
+            Ok(ref a) => a.try_clone().map(Ok),
+            Err(ref b) => b.try_clone().map(Err),
+        }
+    }
 }
 
 macro_rules! arbitrary_tuple {
@@ -222,6 +501,33 @@ macro_rules! arbitrary_tuple {
             fn arbitrary<_U: Unstructured + ?Sized>(u: &mut _U) -> Result<Self, _U::Error> {
                 Ok((Arbitrary::arbitrary(u)?, $($xs::arbitrary(u)?),*))
             }
+
+            // Shrink the first component keeping the rest, then the rest, a
+            // smaller tuple, keeping the first.
+            #[allow(non_snake_case)]
+            fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                let ($x, $($xs),*) = match self.try_clone() {
+                    Some(copy) => copy,
+                    None => return Box::new(iter::empty()),
+                };
+                let rest = ($($xs,)*);
+                let rests = rest.shrink();
+                let firsts = $x.shrink().filter_map(move |first| {
+                    let ($($xs,)*) = rest.try_clone()?;
+                    Some((first, $($xs),*))
+                });
+                let rests = rests.filter_map(move |rest| {
+                    let ($($xs,)*) = rest;
+                    Some(($x.try_clone()?, $($xs),*))
+                });
+                Box::new(firsts.chain(rests))
+            }
+
+            #[allow(non_snake_case)]
+            fn try_clone(&self) -> Option<Self> {
+                let ($x, $($xs),*) = self;
+                Some(($x.try_clone()?, $($xs.try_clone()?),*))
+            }
         }
     };
 }
@@ -236,6 +542,19 @@ macro_rules! arbitrary_array {
                 Ok([Arbitrary::arbitrary(u)?,
                     $(<$ts as Arbitrary>::arbitrary(u)?),*])
             }
+
+            fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                match try_clone_all(self) {
+                    Some(elements) => Box::new(
+                        shrink_each(Rc::new(elements)).filter_map(|x| <[T; $n]>::try_from(x).ok()),
+                    ),
+                    None => Box::new(iter::empty()),
+                }
+            }
+
+            fn try_clone(&self) -> Option<Self> {
+                try_clone_all(self).and_then(|x| <[T; $n]>::try_from(x).ok())
+            }
         }
     };
     ($n: expr,) => {};
@@ -248,6 +567,14 @@ impl<A: Arbitrary> Arbitrary for Vec<A> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(try_clone_all(self))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        try_clone_all(self)
+    }
 }
 
 impl<K: Arbitrary + Ord, V: Arbitrary> Arbitrary for BTreeMap<K, V> {
@@ -255,6 +582,16 @@ impl<K: Arbitrary + Ord, V: Arbitrary> Arbitrary for BTreeMap<K, V> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.try_clone().map(|map| map.into_iter().collect()))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter()
+            .map(|(k, v)| Some((k.try_clone()?, v.try_clone()?)))
+            .collect()
+    }
 }
 
 impl<A: Arbitrary + Ord> Arbitrary for BTreeSet<A> {
@@ -262,6 +599,14 @@ impl<A: Arbitrary + Ord> Arbitrary for BTreeSet<A> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.iter().map(Arbitrary::try_clone).collect())
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter().map(Arbitrary::try_clone).collect()
+    }
 }
 
 impl<A: Arbitrary + Ord> Arbitrary for BinaryHeap<A> {
@@ -269,13 +614,35 @@ impl<A: Arbitrary + Ord> Arbitrary for BinaryHeap<A> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.iter().map(Arbitrary::try_clone).collect())
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter().map(Arbitrary::try_clone).collect()
+    }
 }
 
-impl<K: Arbitrary + Eq + ::std::hash::Hash, V: Arbitrary> Arbitrary for HashMap<K, V> {
+impl<K, V> Arbitrary for HashMap<K, V>
+where
+    K: Arbitrary + Eq + ::std::hash::Hash,
+    V: Arbitrary,
+{
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.try_clone().map(|map| map.into_iter().collect()))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter()
+            .map(|(k, v)| Some((k.try_clone()?, v.try_clone()?)))
+            .collect()
+    }
 }
 
 impl<A: Arbitrary + Eq + ::std::hash::Hash> Arbitrary for HashSet<A> {
@@ -283,6 +650,14 @@ impl<A: Arbitrary + Eq + ::std::hash::Hash> Arbitrary for HashSet<A> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.iter().map(Arbitrary::try_clone).collect())
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter().map(Arbitrary::try_clone).collect()
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for LinkedList<A> {
@@ -290,6 +665,14 @@ impl<A: Arbitrary> Arbitrary for LinkedList<A> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.iter().map(Arbitrary::try_clone).collect())
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter().map(Arbitrary::try_clone).collect()
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for VecDeque<A> {
@@ -297,6 +680,14 @@ impl<A: Arbitrary> Arbitrary for VecDeque<A> {
         let size = u.container_size()?;
         (0..size).map(|_| Arbitrary::arbitrary(u)).collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(self.iter().map(Arbitrary::try_clone).collect())
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.iter().map(Arbitrary::try_clone).collect()
+    }
 }
 
 impl<A> Arbitrary for Cow<'static, A>
@@ -307,6 +698,10 @@ where
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Arbitrary::arbitrary(u).map(Cow::Owned)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl Arbitrary for String {
@@ -316,6 +711,14 @@ impl Arbitrary for String {
             .map(|_| <char as Arbitrary>::arbitrary(u))
             .collect()
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_collection(Some(self.chars().collect()))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl Arbitrary for CString {
@@ -325,36 +728,64 @@ impl Arbitrary for CString {
             Self::new(x).unwrap()
         })
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl Arbitrary for OsString {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         <String as Arbitrary>::arbitrary(u).map(From::from)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl Arbitrary for PathBuf {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         <OsString as Arbitrary>::arbitrary(u).map(From::from)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for Box<A> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Arbitrary::arbitrary(u).map(Self::new)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new((**self).shrink().map(Self::new))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        (**self).try_clone().map(Self::new)
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for Box<[A]> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         <Vec<A> as Arbitrary>::arbitrary(u).map(|x| x.into_boxed_slice())
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        try_clone_all(self).map(Vec::into_boxed_slice)
+    }
 }
 
 impl Arbitrary for Box<str> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         <String as Arbitrary>::arbitrary(u).map(|x| x.into_boxed_str())
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 // impl Arbitrary for Box<CStr> {
@@ -374,12 +805,20 @@ impl<A: Arbitrary> Arbitrary for Arc<A> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Arbitrary::arbitrary(u).map(Self::new)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(Self::clone(self))
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for Rc<A> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Arbitrary::arbitrary(u).map(Self::new)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(Self::clone(self))
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for Cell<A> {
@@ -392,6 +831,10 @@ impl<A: Arbitrary> Arbitrary for RefCell<A> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Arbitrary::arbitrary(u).map(Self::new)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.try_borrow().ok()?.try_clone().map(Self::new)
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for UnsafeCell<A> {
@@ -410,16 +853,104 @@ impl<A: Arbitrary> Arbitrary for iter::Empty<A> {
     fn arbitrary<U: Unstructured + ?Sized>(_: &mut U) -> Result<Self, U::Error> {
         Ok(iter::empty())
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for ::std::marker::PhantomData<A> {
     fn arbitrary<U: Unstructured + ?Sized>(_: &mut U) -> Result<Self, U::Error> {
         Ok(::std::marker::PhantomData)
     }
+
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
 }
 
 impl<A: Arbitrary> Arbitrary for ::std::num::Wrapping<A> {
     fn arbitrary<U: Unstructured + ?Sized>(u: &mut U) -> Result<Self, U::Error> {
         Arbitrary::arbitrary(u).map(::std::num::Wrapping)
     }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.0.shrink().map(::std::num::Wrapping))
+    }
+
+    fn try_clone(&self) -> Option<Self> {
+        self.0.try_clone().map(::std::num::Wrapping)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shrunk<A: Arbitrary>(a: &A) -> Vec<A> {
+        a.shrink().collect()
+    }
+
+    #[test]
+    fn shrink_integers() {
+        assert_eq!(shrunk(&0u8), vec![]);
+        assert_eq!(shrunk(&1u8), vec![0]);
+        assert_eq!(shrunk(&100u32), vec![0, 50, 75, 88, 94, 97, 99]);
+        assert_eq!(shrunk(&-10i64), vec![0, 10, -5, -8, -9]);
+        assert_eq!(shrunk(&i8::MIN)[..2], [0, -64]);
+    }
+
+    #[test]
+    fn shrink_floats_and_chars() {
+        assert_eq!(shrunk(&-2.5f64), vec![0.0, -2.0, 2.5]);
+        assert_eq!(shrunk(&f32::NAN), vec![0.0]);
+        assert_eq!(shrunk(&'\u{2}'), vec!['\u{0}', '\u{1}']);
+    }
+
+    #[test]
+    fn shrink_compound() {
+        assert_eq!(shrunk(&Some(1u8)), vec![None, Some(0)]);
+        assert_eq!(shrunk(&(2u8, true)), vec![(0, true), (1, true), (2, false)]);
+        assert_eq!(shrunk(&(0u8, 200u8))[..2], [(0, 0), (0, 100)]);
+        assert_eq!(shrunk(&[2u8, 1]), vec![[0, 1], [1, 1], [2, 0]]);
+        assert_eq!(
+            shrunk(&vec![2u8, 4]),
+            vec![
+                vec![],
+                vec![4],
+                vec![2],
+                vec![0, 4],
+                vec![1, 4],
+                vec![2, 0],
+                vec![2, 2],
+                vec![2, 3]
+            ]
+        );
+        assert_eq!(
+            shrunk(&vec![0u8, 200])[..4],
+            [vec![], vec![200], vec![0], vec![0, 0]]
+        );
+        assert_eq!(shrunk(&String::from("a\0"))[..4], ["", "\0", "a", "\0\0"]);
+        let map: BTreeMap<u8, u8> = vec![(1, 1)].into_iter().collect();
+        assert_eq!(
+            shrunk(&map),
+            vec![
+                BTreeMap::new(),
+                vec![(0, 1)].into_iter().collect(),
+                vec![(1, 0)].into_iter().collect()
+            ]
+        );
+    }
+
+    #[test]
+    fn shrink_without_clone() {
+        let flags = vec![AtomicBool::new(true)];
+        assert_eq!(flags.shrink().count(), 0);
+        assert_eq!((AtomicBool::new(true), 2u8).shrink().count(), 0);
+        let lens: Vec<usize> = vec![None::<AtomicBool>, None]
+            .shrink()
+            .map(|x| x.len())
+            .collect();
+        assert_eq!(lens, vec![0, 1, 1]);
+    }
 }
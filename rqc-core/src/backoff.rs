@@ -16,11 +16,11 @@ const SIXTEEN: time::Duration = time::Duration::from_millis(16);
 // const FIVEONETWO: time::Duration = time::Duration::from_millis(512);
 
 impl Backoff {
-    pub fn reset(&mut self) -> () {
+    pub fn reset(&mut self) {
         self.count = 0;
     }
 
-    pub fn delay(&mut self) -> () {
+    pub fn delay(&mut self) {
         match self.count {
             0 => (),
             1 => thread::sleep(ONE),
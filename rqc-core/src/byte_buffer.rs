@@ -1,4 +1,5 @@
 use crate::arbitrary::{Arbitrary, Unstructured};
+use std::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 /// Potential errors of the [`ByteBuffer`]
@@ -16,6 +17,7 @@ pub struct FiniteByteBuffer<'a> {
     buffer: &'a [u8],
     offset: usize,
     container_size_limit: usize,
+    values: Vec<String>,
 }
 
 impl<'a> FiniteByteBuffer<'a> {
@@ -25,6 +27,7 @@ impl<'a> FiniteByteBuffer<'a> {
             offset: 0,
             buffer,
             container_size_limit: 256,
+            values: Vec::new(),
         }
     }
 
@@ -33,6 +36,25 @@ impl<'a> FiniteByteBuffer<'a> {
         self.container_size_limit = csl;
         self
     }
+
+    /// Generate an arbitrary value, recording it under `name` as with
+    /// [`FiniteByteBuffer::record`]
+    pub fn generate<T: Arbitrary + fmt::Debug>(&mut self, name: &str) -> Result<T, BufferOpError> {
+        let value = T::arbitrary(self)?;
+        self.record(name, &value);
+        Ok(value)
+    }
+
+    /// Record the `Debug` rendering of `value` as `name = value`, to be
+    /// reported should the test fail
+    pub fn record<T: fmt::Debug + ?Sized>(&mut self, name: &str, value: &T) {
+        self.values.push(format!("{} = {:?}", name, value));
+    }
+
+    /// Every value recorded so far, in the order recorded
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
 }
 
 impl<'a> Unstructured for FiniteByteBuffer<'a> {
@@ -61,49 +83,50 @@ mod test {
 
     #[test]
     fn byte_buffer_fill_buffer() {
-        let x = [1, 2, 3, 4];
-        let mut rb = ByteBuffer::new(&x).unwrap();
-        let mut z = [0; 10];
-        rb.fill_buffer(&mut z).unwrap();
-        assert_eq!(z, [1, 2, 3, 4, 1, 2, 3, 4, 1, 2]);
-        rb.fill_buffer(&mut z).unwrap();
-        assert_eq!(z, [3, 4, 1, 2, 3, 4, 1, 2, 3, 4]);
-    }
-
-    #[test]
-    fn byte_buffer_fill_buffer_shrink() {
-        let x = [1, 2, 3, 4];
-        let mut rb = ByteBuffer::new(&x).unwrap();
-        let mut z = [0; 10];
-        assert_eq!(2, rb.shrink());
+        let x = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let mut rb = FiniteByteBuffer::new(&x);
+        let mut z = [0; 4];
         rb.fill_buffer(&mut z).unwrap();
-        assert_eq!(z, [1, 2, 1, 2, 1, 2, 1, 2, 1, 2]);
-        assert_eq!(1, rb.shrink());
+        assert_eq!(z, [1, 2, 3, 4]);
         rb.fill_buffer(&mut z).unwrap();
-        assert_eq!(z, [1, 1, 1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(z, [5, 6, 7, 8]);
     }
 
     #[test]
-    fn byte_buffer_fill_buffer_shift() {
+    fn byte_buffer_fill_buffer_insufficient() {
         let x = [1, 2, 3, 4];
-        let mut rb = ByteBuffer::new(&x).unwrap();
-        let mut z = [0; 10];
-        rb.shift_right(1).unwrap();
+        let mut rb = FiniteByteBuffer::new(&x);
+        let mut z = [0; 3];
         rb.fill_buffer(&mut z).unwrap();
-        assert_eq!(z, [2, 3, 4, 1, 2, 3, 4, 1, 2, 3]);
-        rb.shift_right(1).unwrap();
-        rb.fill_buffer(&mut z).unwrap();
-        assert_eq!(z, [1, 2, 3, 4, 1, 2, 3, 4, 1, 2]);
+        assert_eq!(z, [1, 2, 3]);
+        assert_eq!(
+            rb.fill_buffer(&mut z),
+            Err(BufferOpError::InsufficientBytes)
+        );
     }
 
     #[test]
     fn byte_buffer_container_size() {
-        let x = [1, 2, 3, 4, 5];
-        let mut rb = ByteBuffer::new(&x).unwrap().container_size_limit(11);
+        let x = [9, 0, 0, 0, 0, 0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0];
+        let mut rb = FiniteByteBuffer::new(&x).container_size_limit(11);
         assert_eq!(rb.container_size().unwrap(), 9);
         assert_eq!(rb.container_size().unwrap(), 1);
-        assert_eq!(rb.container_size().unwrap(), 2);
-        assert_eq!(rb.container_size().unwrap(), 6);
-        assert_eq!(rb.container_size().unwrap(), 1);
+        assert_eq!(rb.container_size(), Err(BufferOpError::InsufficientBytes));
+    }
+
+    #[test]
+    fn byte_buffer_generate_records_values() {
+        let x = [17, 200];
+        let mut rb = FiniteByteBuffer::new(&x);
+        let lhs: u8 = rb.generate("lhs").unwrap();
+        let rhs: Option<u8> = rb.generate("rhs").unwrap();
+        assert_eq!((lhs, rhs), (17, None));
+        rb.record("sum", &(u16::from(lhs) + 1));
+        assert_eq!(rb.values(), ["lhs = 17", "rhs = None", "sum = 18"]);
+        assert_eq!(
+            rb.generate::<u8>("more"),
+            Err(BufferOpError::InsufficientBytes)
+        );
+        assert_eq!(rb.values().len(), 3);
     }
 }
@@ -1,24 +1,231 @@
-use std::{io, mem, ptr};
+use crate::coverage::{self, COVERAGE_MAP_SIZE};
+use crate::wait::{futex_wake, Waiter};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+use std::{error, fmt, io, ptr, slice};
 
 pub struct Comm {
     ptr: *mut u64,
-    //    len: usize,
+    len: usize,
+    layout: Layout,
+    capabilities: u64,
 }
 
-const SERVER_STATUS_OFFSET: isize = 0;
-const CLIENT_STATUS_OFFSET: isize = 1;
-const BYTE_POOL_SIZE_OFFSET: isize = 2;
-const BYTE_POOL_OFFSET: isize = 3;
+// NOTE -- the segment opens with a header of HEADER_SIZE bytes, a run of
+// u64 words at the offsets below. The server fills it in before starting the
+// client, storing MAGIC last, and the client checks it all on attach. After
+// the header come the regions, each placed by an offset and size pair in the
+// header: the byte pool, then the property, values and panic regions -- each
+// a length word followed by that many bytes of utf8 -- and last the coverage
+// region.
+//
+// The header words are only ever touched atomically. The byte pool size is
+// stored, with release ordering, after the pool is filled, and each status
+// word likewise after whatever it announces. Futexes are 32 bits wide, so the
+// status words are only ever touched through the first four bytes of their
+// eight byte slots.
+const MAGIC_OFFSET: isize = 0;
+const VERSION_OFFSET: isize = 1;
+const SERVER_STATUS_OFFSET: isize = 2;
+const CLIENT_STATUS_OFFSET: isize = 3;
+const BYTE_POOL_SIZE_OFFSET: isize = 4;
+const SEGMENT_SIZE_OFFSET: isize = 5;
+/// The first of the offset and size pairs placing each region, in the order
+/// of `Layout::regions`
+const REGIONS_OFFSET: isize = 6;
+const SERVER_CAPABILITIES_OFFSET: isize = 16;
+const CLIENT_VERSION_OFFSET: isize = 17;
+const CLIENT_CAPABILITIES_OFFSET: isize = 18;
+// NOTE -- the magic and version words and the client's hello words must
+// stay where they are in every protocol version, else a client can't tell the
+// server it speaks another.
 
-const SERVER_DEFAULT: u64 = 0;
-const SERVER_READY: u64 = 1;
+/// The bytes reserved for the header at the start of the segment
+const HEADER_SIZE: usize = 256;
 
-const CLIENT_DEFAULT: u64 = 0;
-const CLIENT_READY: u64 = 1;
-const CLIENT_TEST_PASSED: u64 = 2;
-const CLIENT_TEST_SKIPPED: u64 = 3;
-const CLIENT_TEST_FAILED: u64 = 4;
-const CLIENT_TEST_INSUFFICIENT_BYTES: u64 = 5;
+/// The first word of every segment set up by a server, "RQC-SHM" and a nul
+pub const MAGIC: u64 = 0x004d_4853_2d43_5152;
+/// The version of the layout and handshake spoken through the segment
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// The client records edge coverage into the coverage region
+pub const CAP_COVERAGE: u64 = 1 << 0;
+/// The client leaves panic messages in the panic region
+pub const CAP_PANIC_MESSAGES: u64 = 1 << 1;
+/// The client runs the property the server names, not only its first
+pub const CAP_PROPERTIES: u64 = 1 << 2;
+/// The client leaves the values of failing tests in the values region
+pub const CAP_VALUES: u64 = 1 << 3;
+/// Every capability this build of rqc-core has
+pub const CAPABILITIES: u64 = CAP_COVERAGE | CAP_PANIC_MESSAGES | CAP_PROPERTIES | CAP_VALUES;
+
+/// The maximum length in bytes of a panic message passed back to the server
+pub const PANIC_MESSAGE_SIZE: usize = 1024;
+
+/// The maximum length in bytes of the generated values passed back to the
+/// server
+pub const VALUES_SIZE: usize = 4096;
+
+/// The maximum length in bytes of a property name
+pub const PROPERTY_NAME_SIZE: usize = 256;
+
+/// The size in bytes of a segment with room for a byte pool of
+/// `byte_pool_size` bytes
+pub fn segment_size(byte_pool_size: usize) -> usize {
+    Layout::new(byte_pool_size).segment_size()
+}
+
+/// What went wrong setting up, attaching to or moving bytes through a segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommError {
+    /// The segment is too small for the layout
+    SegmentTooSmall { len: usize, needed: usize },
+    /// The segment does not start with [`MAGIC`], so no server set it up
+    BadMagic(u64),
+    /// The segment was set up for another protocol version
+    VersionMismatch { server: u64, client: u64 },
+    /// The client has not said which protocol version it speaks
+    NoHandshake,
+    /// The header places the named region outside the segment, over the
+    /// header or over another region
+    BadRegion(&'static str),
+    /// A status word holds a value no status has, written by a peer speaking
+    /// something else or not speaking at all
+    BadStatus(u32),
+    /// More bytes than the named region or buffer holds
+    Overflow {
+        region: &'static str,
+        len: usize,
+        capacity: usize,
+    },
+}
+
+impl fmt::Display for CommError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommError::SegmentTooSmall { len, needed } => {
+                write!(f, "segment is {} bytes but needs at least {}", len, needed)
+            }
+            CommError::BadMagic(magic) => write!(
+                f,
+                "segment starts with {:#018x}, not {:#018x}; it was not set up by an rqc server",
+                magic, MAGIC
+            ),
+            CommError::VersionMismatch { server, client } => write!(
+                f,
+                "server speaks protocol version {}, client speaks {}",
+                server, client
+            ),
+            CommError::NoHandshake => write!(f, "client did not say which protocol it speaks"),
+            CommError::BadRegion(region) => write!(f, "{} region is out of bounds", region),
+            CommError::BadStatus(status) => {
+                write!(f, "status word holds unknown status {}", status)
+            }
+            CommError::Overflow {
+                region,
+                len,
+                capacity,
+            } => write!(
+                f,
+                "{} bytes do not fit the {} bytes of the {}",
+                len, capacity, region
+            ),
+        }
+    }
+}
+
+impl error::Error for CommError {}
+
+/// A span of the segment, in bytes from its start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Where each region of the segment lies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub byte_pool: Region,
+    pub property: Region,
+    pub values: Region,
+    pub panic: Region,
+    pub coverage: Region,
+}
+
+impl Layout {
+    /// The layout of a segment with room for a byte pool of `byte_pool_size`
+    /// bytes, every region following the last
+    pub fn new(byte_pool_size: usize) -> Layout {
+        let mut offset = HEADER_SIZE;
+        let mut next = |size: usize| {
+            let region = Region { offset, size };
+            // keep the length words of the string regions aligned
+            offset += (size + 7) & !7;
+            region
+        };
+        Layout {
+            byte_pool: next(byte_pool_size),
+            property: next(8 + PROPERTY_NAME_SIZE),
+            values: next(8 + VALUES_SIZE),
+            panic: next(8 + PANIC_MESSAGE_SIZE),
+            coverage: next(COVERAGE_MAP_SIZE),
+        }
+    }
+
+    /// The size in bytes of a segment holding every region
+    pub fn segment_size(&self) -> usize {
+        self.coverage.offset + self.coverage.size
+    }
+
+    fn regions(&self) -> [(&'static str, Region); 5] {
+        [
+            ("byte pool", self.byte_pool),
+            ("property", self.property),
+            ("values", self.values),
+            ("panic", self.panic),
+            ("coverage", self.coverage),
+        ]
+    }
+
+    /// Check that every region lies within a segment of `len` bytes, past the
+    /// header and clear of every other
+    fn validate(&self, len: usize) -> Result<(), CommError> {
+        let regions = self.regions();
+        for (idx, &(name, region)) in regions.iter().enumerate() {
+            let end = match region.offset.checked_add(region.size) {
+                Some(end) if region.offset >= HEADER_SIZE && end <= len => end,
+                _ => return Err(CommError::BadRegion(name)),
+            };
+            let overlaps = regions[..idx]
+                .iter()
+                .any(|&(_, other)| region.offset < other.offset + other.size && other.offset < end);
+            if overlaps {
+                return Err(CommError::BadRegion(name));
+            }
+        }
+        for &(name, region) in &regions[1..4] {
+            if region.size < 8 || region.offset % 8 != 0 {
+                return Err(CommError::BadRegion(name));
+            }
+        }
+        if self.coverage.size != COVERAGE_MAP_SIZE {
+            return Err(CommError::BadRegion("coverage"));
+        }
+        Ok(())
+    }
+}
+
+const SERVER_DEFAULT: u32 = 0;
+const SERVER_READY: u32 = 1;
+
+const CLIENT_DEFAULT: u32 = 0;
+const CLIENT_READY: u32 = 1;
+const CLIENT_TEST_PASSED: u32 = 2;
+const CLIENT_TEST_SKIPPED: u32 = 3;
+const CLIENT_TEST_FAILED: u32 = 4;
+const CLIENT_TEST_INSUFFICIENT_BYTES: u32 = 5;
+const CLIENT_TEST_PANICKED: u32 = 6;
 
 #[derive(Debug)]
 pub enum ClientStatus {
@@ -39,6 +246,7 @@ pub enum TestStatus {
     Failed,
     Skipped,
     InsufficientBytes,
+    Panicked,
 }
 
 /*
@@ -60,23 +268,32 @@ target
       -> test run, signals
       -> set(CLIENT_TEST_PASSED) | set(CLIENT_TEST_SKIPPED) |
          set(CLIENT_TEST_FAILED) | set(CLIENT_TEST_INSUFFICIENT_BYTES) |
+         set panic message, set(CLIENT_TEST_PANICKED) |
          exit 0 (skipped) | exit non-zero (failed)
       -> goto 0
 
 */
 
 impl io::Write for Comm {
+    /// Fill the byte pool with `buf`, which must fit it whole
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let total_bytes = buf.len();
-        // TODO(blt) -- check that the buffer being written isn't too big for the space we have
+        let pool = self.layout.byte_pool;
+        if total_bytes > pool.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                CommError::Overflow {
+                    region: "byte pool",
+                    len: total_bytes,
+                    capacity: pool.size,
+                },
+            ));
+        }
         unsafe {
-            *self.ptr.offset(BYTE_POOL_SIZE_OFFSET) = total_bytes as u64;
-            ptr::copy_nonoverlapping(
-                buf.as_ptr(),
-                self.ptr.offset(BYTE_POOL_OFFSET) as *mut u8,
-                total_bytes,
-            );
+            ptr::copy_nonoverlapping(buf.as_ptr(), self.region_ptr(pool), total_bytes);
         }
+        self.header_word(BYTE_POOL_SIZE_OFFSET)
+            .store(total_bytes as u64, Ordering::Release);
         Ok(total_bytes)
     }
 
@@ -86,88 +303,609 @@ impl io::Write for Comm {
 }
 
 impl io::Read for Comm {
+    /// Copy the whole of the byte pool into `buf`, which must have room for it
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let total_bytes = self
+            .header_word(BYTE_POOL_SIZE_OFFSET)
+            .load(Ordering::Acquire) as usize;
+        let pool = self.layout.byte_pool;
+        let (region, capacity, kind) = if total_bytes > pool.size {
+            ("byte pool", pool.size, io::ErrorKind::InvalidData)
+        } else {
+            ("read buffer", buf.len(), io::ErrorKind::InvalidInput)
+        };
+        if total_bytes > capacity {
+            return Err(io::Error::new(
+                kind,
+                CommError::Overflow {
+                    region,
+                    len: total_bytes,
+                    capacity,
+                },
+            ));
+        }
         unsafe {
-            let total_bytes =
-                *self.ptr.offset(BYTE_POOL_SIZE_OFFSET) as usize * mem::size_of::<u64>();
-            ptr::copy_nonoverlapping(
-                self.ptr.offset(BYTE_POOL_OFFSET) as *mut u8,
-                buf.as_mut_ptr(),
-                total_bytes,
-            );
-            Ok(total_bytes)
+            ptr::copy_nonoverlapping(self.region_ptr(pool), buf.as_mut_ptr(), total_bytes);
         }
+        Ok(total_bytes)
     }
 }
 
 // NOTE(blt) -- all of this could be made much more compact, stuffing multiple
 // signals into words and what not
 impl Comm {
-    pub fn new(ptr: *mut libc::c_void, len: usize) -> Self {
+    /// Set up the header of the `len` byte segment at `ptr` for a new client,
+    /// giving the byte pool all the room the other regions leave
+    pub fn create(ptr: *mut libc::c_void, len: usize) -> Result<Comm, CommError> {
         assert!(::std::mem::size_of::<usize>() == 8);
-        assert!(len > 128); // TODO(blt) -- make this a real bound
-        Self {
+        let needed = segment_size(0);
+        if len < needed {
+            return Err(CommError::SegmentTooSmall { len, needed });
+        }
+        // every region is a whole number of words, round the pool down so
+        // the layout cannot end past the segment
+        let layout = Layout::new((len - needed) & !7);
+        layout.validate(len)?;
+        let mut comm = Comm {
+            ptr: ptr as *mut u64,
+            len,
+            layout,
+            capabilities: CAPABILITIES,
+        };
+        comm.header_word(VERSION_OFFSET)
+            .store(PROTOCOL_VERSION, Ordering::Relaxed);
+        comm.header_word(SEGMENT_SIZE_OFFSET)
+            .store(len as u64, Ordering::Relaxed);
+        comm.header_word(BYTE_POOL_SIZE_OFFSET)
+            .store(0, Ordering::Relaxed);
+        comm.status_word(SERVER_STATUS_OFFSET)
+            .store(SERVER_DEFAULT, Ordering::Relaxed);
+        comm.status_word(CLIENT_STATUS_OFFSET)
+            .store(CLIENT_DEFAULT, Ordering::Relaxed);
+        comm.header_word(SERVER_CAPABILITIES_OFFSET)
+            .store(CAPABILITIES, Ordering::Relaxed);
+        comm.handshake_reset();
+        for (idx, &(_, region)) in comm.layout.regions().iter().enumerate() {
+            let word = REGIONS_OFFSET + 2 * idx as isize;
+            comm.header_word(word)
+                .store(region.offset as u64, Ordering::Relaxed);
+            comm.header_word(word + 1)
+                .store(region.size as u64, Ordering::Relaxed);
+        }
+        for &(_, region) in &comm.layout.regions()[1..4] {
+            unsafe { ptr::write_unaligned(comm.region_ptr(region) as *mut u64, 0) };
+        }
+        comm.header_word(MAGIC_OFFSET)
+            .store(MAGIC, Ordering::Release);
+        Ok(comm)
+    }
+
+    /// Attach to the `len` byte segment at `ptr` set up by a server, checking
+    /// its header
+    ///
+    /// The client first tells the server which protocol version it speaks and
+    /// what it can do, so that a server of another version can refuse it
+    /// clearly. The two then keep to the capabilities they share.
+    pub fn attach(ptr: *mut libc::c_void, len: usize) -> Result<Comm, CommError> {
+        assert!(::std::mem::size_of::<usize>() == 8);
+        if len < HEADER_SIZE {
+            return Err(CommError::SegmentTooSmall {
+                len,
+                needed: HEADER_SIZE,
+            });
+        }
+        let mut comm = Comm {
             ptr: ptr as *mut u64,
-            // len,
+            len,
+            layout: Layout::new(0),
+            capabilities: 0,
+        };
+        let magic = comm.header_word(MAGIC_OFFSET).load(Ordering::Acquire);
+        if magic != MAGIC {
+            return Err(CommError::BadMagic(magic));
         }
+        comm.header_word(CLIENT_CAPABILITIES_OFFSET)
+            .store(CAPABILITIES, Ordering::Relaxed);
+        comm.header_word(CLIENT_VERSION_OFFSET)
+            .store(PROTOCOL_VERSION, Ordering::Release);
+        let version = comm.header_word(VERSION_OFFSET).load(Ordering::Relaxed);
+        if version != PROTOCOL_VERSION {
+            return Err(CommError::VersionMismatch {
+                server: version,
+                client: PROTOCOL_VERSION,
+            });
+        }
+        let needed = comm
+            .header_word(SEGMENT_SIZE_OFFSET)
+            .load(Ordering::Relaxed) as usize;
+        if len < needed {
+            return Err(CommError::SegmentTooSmall { len, needed });
+        }
+        let region = |idx: isize| {
+            let word = REGIONS_OFFSET + 2 * idx;
+            Region {
+                offset: comm.header_word(word).load(Ordering::Relaxed) as usize,
+                size: comm.header_word(word + 1).load(Ordering::Relaxed) as usize,
+            }
+        };
+        let layout = Layout {
+            byte_pool: region(0),
+            property: region(1),
+            values: region(2),
+            panic: region(3),
+            coverage: region(4),
+        };
+        layout.validate(len)?;
+        comm.layout = layout;
+        comm.capabilities = CAPABILITIES
+            & comm
+                .header_word(SERVER_CAPABILITIES_OFFSET)
+                .load(Ordering::Relaxed);
+        Ok(comm)
+    }
+
+    /// Check the hello of a newly attached client, keeping to the
+    /// capabilities both sides share
+    ///
+    /// Returns the shared capabilities. Fails if the client has not yet said
+    /// hello or speaks another protocol version.
+    pub fn negotiate(&mut self) -> Result<u64, CommError> {
+        match self.client_version() {
+            0 => return Err(CommError::NoHandshake),
+            PROTOCOL_VERSION => (),
+            client => {
+                return Err(CommError::VersionMismatch {
+                    server: PROTOCOL_VERSION,
+                    client,
+                })
+            }
+        }
+        self.capabilities = CAPABILITIES
+            & self
+                .header_word(CLIENT_CAPABILITIES_OFFSET)
+                .load(Ordering::Relaxed);
+        Ok(self.capabilities)
+    }
+
+    /// The protocol version the client said it speaks, zero if it has said
+    /// nothing yet
+    pub fn client_version(&self) -> u64 {
+        self.header_word(CLIENT_VERSION_OFFSET)
+            .load(Ordering::Acquire)
+    }
+
+    /// Forget the hello of the last client, before starting another
+    pub fn handshake_reset(&mut self) {
+        self.header_word(CLIENT_VERSION_OFFSET)
+            .store(0, Ordering::Relaxed);
+        self.header_word(CLIENT_CAPABILITIES_OFFSET)
+            .store(0, Ordering::Relaxed);
+        self.capabilities = CAPABILITIES;
+    }
+
+    /// Whether both sides have every capability in `capabilities`
+    pub fn supports(&self, capabilities: u64) -> bool {
+        self.capabilities & capabilities == capabilities
+    }
+
+    /// The largest input the byte pool holds
+    pub fn byte_pool_capacity(&self) -> usize {
+        self.layout.byte_pool.size
+    }
+
+    fn header_word(&self, offset: isize) -> &AtomicU64 {
+        unsafe { &*(self.ptr.offset(offset) as *const AtomicU64) }
+    }
+
+    fn status_word(&self, offset: isize) -> &AtomicU32 {
+        unsafe { &*(self.ptr.offset(offset) as *const AtomicU32) }
+    }
+
+    /// Store `stat` in the status word at `offset` and wake the other side
+    fn set_status(&self, offset: isize, stat: u32) {
+        let word = self.status_word(offset);
+        word.store(stat, Ordering::Release);
+        futex_wake(word);
+    }
+
+    fn region_ptr(&self, region: Region) -> *mut u8 {
+        debug_assert!(region.offset + region.size <= self.len);
+        unsafe { (self.ptr as *mut u8).add(region.offset) }
+    }
+
+    fn coverage_ptr(&self) -> *mut u8 {
+        self.region_ptr(self.layout.coverage)
     }
 
     //
-    // server
+    // property
 
-    fn server_set_status(&mut self, stat: u64) -> () {
+    /// The name of the property the server wants run, empty if it has no
+    /// preference
+    pub fn property(&self) -> String {
+        if !self.supports(CAP_PROPERTIES) {
+            return String::new();
+        }
+        self.read_str(self.layout.property)
+    }
+    pub fn server_property(&mut self, name: &str) -> Result<(), CommError> {
+        let capacity = self.layout.property.size - 8;
+        if name.len() > capacity {
+            return Err(CommError::Overflow {
+                region: "property region",
+                len: name.len(),
+                capacity,
+            });
+        }
+        self.write_str(self.layout.property, name);
+        Ok(())
+    }
+
+    //
+    // panic message
+
+    /// The panic message the client left with its last
+    /// `TestStatus::Panicked`
+    pub fn panic_message(&self) -> String {
+        if !self.supports(CAP_PANIC_MESSAGES) {
+            return String::new();
+        }
+        self.read_str(self.layout.panic)
+    }
+    pub fn client_panic_message(&mut self, message: &str) {
+        if self.supports(CAP_PANIC_MESSAGES) {
+            self.write_str(self.layout.panic, message)
+        }
+    }
+
+    //
+    // values
+
+    /// The generated values the client left with its last failing test, one
+    /// rendering per value
+    pub fn values(&self) -> Vec<String> {
+        if !self.supports(CAP_VALUES) {
+            return Vec::new();
+        }
+        let values = self.read_str(self.layout.values);
+        values.lines().map(String::from).collect()
+    }
+    pub fn client_values(&mut self, values: &[String]) {
+        if self.supports(CAP_VALUES) {
+            self.write_str(self.layout.values, &values.join("\n"))
+        }
+    }
+
+    //
+    // coverage
+
+    /// The edge counters recorded by the client during the last test
+    pub fn coverage(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.coverage_ptr(), COVERAGE_MAP_SIZE) }
+    }
+    pub fn coverage_reset(&mut self) {
+        unsafe {
+            ptr::write_bytes(self.coverage_ptr(), 0, COVERAGE_MAP_SIZE);
+        }
+    }
+    pub fn coverage_enable(&mut self) {
+        if self.supports(CAP_COVERAGE) {
+            unsafe {
+                coverage::enable(self.coverage_ptr());
+            }
+        }
+    }
+    pub fn coverage_disable(&mut self) {
+        coverage::disable();
+    }
+
+    /// Read the string stored in `region`, a length word followed by at most
+    /// the rest of the region in utf8
+    fn read_str(&self, region: Region) -> String {
+        let ptr = self.region_ptr(region);
         unsafe {
-            *self.ptr.offset(SERVER_STATUS_OFFSET) = stat;
+            let len = ::std::cmp::min(ptr::read(ptr as *const u64) as usize, region.size - 8);
+            let bytes = slice::from_raw_parts(ptr.add(8), len);
+            String::from_utf8_lossy(bytes).into_owned()
         }
     }
-    pub fn server_reset(&mut self) -> () {
+
+    /// Store `s` in `region`, cut short on a char boundary if it does not fit
+    fn write_str(&self, region: Region, s: &str) {
+        let mut len = ::std::cmp::min(s.len(), region.size - 8);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        let ptr = self.region_ptr(region);
+        unsafe {
+            ptr::copy_nonoverlapping(s.as_ptr(), ptr.add(8), len);
+            ptr::write(ptr as *mut u64, len as u64);
+        }
+    }
+
+    //
+    // server
+
+    fn server_set_status(&mut self, stat: u32) {
+        self.set_status(SERVER_STATUS_OFFSET, stat);
+    }
+    pub fn server_reset(&mut self) {
         self.server_set_status(SERVER_DEFAULT);
     }
-    pub fn server_ready(&mut self) -> () {
+    pub fn server_ready(&mut self) {
         self.server_set_status(SERVER_READY);
     }
-    pub fn server_status(&self) -> ServerStatus {
-        let status = unsafe { *self.ptr.offset(SERVER_STATUS_OFFSET) };
+    pub fn server_status(&self) -> Result<ServerStatus, CommError> {
+        let status = self
+            .status_word(SERVER_STATUS_OFFSET)
+            .load(Ordering::Acquire);
         match status {
-            SERVER_DEFAULT => ServerStatus::Default,
-            SERVER_READY => ServerStatus::Ready,
-            _ => unreachable!(),
+            SERVER_DEFAULT => Ok(ServerStatus::Default),
+            SERVER_READY => Ok(ServerStatus::Ready),
+            _ => Err(CommError::BadStatus(status)),
         }
     }
+    /// Wait for the server status to change from `seen`, see [`Waiter::wait`]
+    pub fn server_wait(&self, seen: &ServerStatus, waiter: &mut Waiter, timeout: Option<Duration>) {
+        let seen = match *seen {
+            ServerStatus::Default => SERVER_DEFAULT,
+            ServerStatus::Ready => SERVER_READY,
+        };
+        waiter.wait(self.status_word(SERVER_STATUS_OFFSET), seen, timeout);
+    }
 
     //
     // client
 
-    fn client_set_status(&mut self, stat: u64) -> () {
-        unsafe {
-            *self.ptr.offset(CLIENT_STATUS_OFFSET) = stat;
-        }
+    fn client_set_status(&mut self, stat: u32) {
+        self.set_status(CLIENT_STATUS_OFFSET, stat);
     }
-    pub fn client_reset(&mut self) -> () {
+    pub fn client_reset(&mut self) {
         self.client_set_status(CLIENT_DEFAULT);
     }
-    pub fn client_ready(&mut self) -> () {
+    pub fn client_ready(&mut self) {
         self.client_set_status(CLIENT_READY);
     }
-    pub fn client_test_status(&mut self, status: TestStatus) -> () {
-        let s = match status {
-            TestStatus::Passed => CLIENT_TEST_PASSED,
-            TestStatus::Skipped => CLIENT_TEST_SKIPPED,
-            TestStatus::Failed => CLIENT_TEST_FAILED,
-            TestStatus::InsufficientBytes => CLIENT_TEST_INSUFFICIENT_BYTES,
-        };
-        self.client_set_status(s);
+    pub fn client_test_status(&mut self, status: TestStatus) {
+        self.client_set_status(test_status_word(&status));
     }
-    pub fn client_status(&self) -> ClientStatus {
-        let status = unsafe { *self.ptr.offset(CLIENT_STATUS_OFFSET) };
-        match status {
+    pub fn client_status(&self) -> Result<ClientStatus, CommError> {
+        let status = self
+            .status_word(CLIENT_STATUS_OFFSET)
+            .load(Ordering::Acquire);
+        Ok(match status {
             CLIENT_DEFAULT => ClientStatus::Default,
             CLIENT_READY => ClientStatus::Ready,
             CLIENT_TEST_PASSED => ClientStatus::Test(TestStatus::Passed),
             CLIENT_TEST_SKIPPED => ClientStatus::Test(TestStatus::Skipped),
             CLIENT_TEST_FAILED => ClientStatus::Test(TestStatus::Failed),
             CLIENT_TEST_INSUFFICIENT_BYTES => ClientStatus::Test(TestStatus::InsufficientBytes),
-            _ => unreachable!(),
+            CLIENT_TEST_PANICKED => ClientStatus::Test(TestStatus::Panicked),
+            _ => return Err(CommError::BadStatus(status)),
+        })
+    }
+    /// Wait for the client status to change from `seen`, see [`Waiter::wait`]
+    pub fn client_wait(&self, seen: &ClientStatus, waiter: &mut Waiter, timeout: Option<Duration>) {
+        let seen = match *seen {
+            ClientStatus::Default => CLIENT_DEFAULT,
+            ClientStatus::Ready => CLIENT_READY,
+            ClientStatus::Test(ref status) => test_status_word(status),
+        };
+        waiter.wait(self.status_word(CLIENT_STATUS_OFFSET), seen, timeout);
+    }
+}
+
+fn test_status_word(status: &TestStatus) -> u32 {
+    match *status {
+        TestStatus::Passed => CLIENT_TEST_PASSED,
+        TestStatus::Skipped => CLIENT_TEST_SKIPPED,
+        TestStatus::Failed => CLIENT_TEST_FAILED,
+        TestStatus::InsufficientBytes => CLIENT_TEST_INSUFFICIENT_BYTES,
+        TestStatus::Panicked => CLIENT_TEST_PANICKED,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wait::Waiter;
+    use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::unistd::{fork, ForkResult};
+    use std::io::{Read, Write};
+
+    const ROUNDS: usize = 10_000;
+    const MAX_BYTES: usize = 512;
+
+    /// The bytes of round `round`, every one of them the length less one so
+    /// that a read mixing two rounds is caught
+    fn round_bytes(round: usize, bytes: &mut [u8]) -> &[u8] {
+        let len = 1 + round % MAX_BYTES;
+        for byte in &mut bytes[..len] {
+            *byte = (len - 1) as u8;
+        }
+        &bytes[..len]
+    }
+
+    /// Play the target for `ROUNDS` rounds, failing any round whose bytes are
+    /// not all of a piece
+    ///
+    /// Runs in a forked child so allocates nothing.
+    fn client(comm: &mut Comm, bytes: &mut [u8]) {
+        let mut waiter = Waiter::default();
+        for _ in 0..ROUNDS {
+            comm.client_ready();
+            loop {
+                match comm.server_status().unwrap() {
+                    ServerStatus::Ready => break,
+                    seen => comm.server_wait(&seen, &mut waiter, None),
+                }
+            }
+            waiter.reset();
+            let len = comm.read(bytes).unwrap_or(0);
+            let whole = len > 0 && bytes[..len].iter().all(|b| *b == (len - 1) as u8);
+            comm.client_test_status(if whole {
+                TestStatus::Passed
+            } else {
+                TestStatus::Failed
+            });
+            loop {
+                match comm.server_status().unwrap() {
+                    ServerStatus::Default => break,
+                    seen => comm.server_wait(&seen, &mut waiter, None),
+                }
+            }
+            waiter.reset();
+        }
+    }
+
+    #[test]
+    fn comm_attach_checks_header() {
+        let len = segment_size(100);
+        let mut segment = vec![0u64; len / 8];
+        let ptr = segment.as_mut_ptr() as *mut libc::c_void;
+        assert_eq!(Comm::attach(ptr, len).err(), Some(CommError::BadMagic(0)));
+
+        let mut server = Comm::create(ptr, len).unwrap();
+        assert_eq!(server.byte_pool_capacity(), 104);
+        server.write_all(&[7; 104]).unwrap();
+        let mut client = Comm::attach(ptr, len).unwrap();
+        let mut bytes = [0; 104];
+        assert_eq!(client.read(&mut bytes).unwrap(), 104);
+        assert_eq!(bytes[103], 7);
+        assert!(client.read(&mut [0; 8]).is_err());
+        assert!(server.write(&[0; 105]).is_err());
+        assert!(server
+            .server_property(&"x".repeat(PROPERTY_NAME_SIZE + 1))
+            .is_err());
+
+        assert_eq!(
+            Comm::attach(ptr, len - 8).err(),
+            Some(CommError::SegmentTooSmall {
+                len: len - 8,
+                needed: len
+            })
+        );
+        segment[VERSION_OFFSET as usize] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            Comm::attach(ptr, len).err(),
+            Some(CommError::VersionMismatch {
+                server: PROTOCOL_VERSION + 1,
+                client: PROTOCOL_VERSION
+            })
+        );
+        segment[VERSION_OFFSET as usize] = PROTOCOL_VERSION;
+        // values region moved over the property region
+        segment[REGIONS_OFFSET as usize + 4] -= 8;
+        assert_eq!(
+            Comm::attach(ptr, len).err(),
+            Some(CommError::BadRegion("values"))
+        );
+    }
+
+    #[test]
+    fn comm_create_stays_within_odd_segment() {
+        let len = segment_size(0) + 3;
+        let mut segment = vec![0u64; len / 8 + 1];
+        let ptr = segment.as_mut_ptr() as *mut libc::c_void;
+        let server = Comm::create(ptr, len).unwrap();
+        assert_eq!(server.byte_pool_capacity(), 0);
+        assert!(server.layout.segment_size() <= len);
+    }
+
+    #[test]
+    fn comm_rejects_unknown_status() {
+        let len = segment_size(0);
+        let mut segment = vec![0u64; len / 8];
+        let ptr = segment.as_mut_ptr() as *mut libc::c_void;
+        let comm = Comm::create(ptr, len).unwrap();
+        segment[CLIENT_STATUS_OFFSET as usize] = 99;
+        segment[SERVER_STATUS_OFFSET as usize] = 42;
+        assert_eq!(comm.client_status().err(), Some(CommError::BadStatus(99)));
+        assert_eq!(comm.server_status().err(), Some(CommError::BadStatus(42)));
+    }
+
+    #[test]
+    fn comm_negotiate_shares_capabilities() {
+        let len = segment_size(0);
+        let mut segment = vec![0u64; len / 8];
+        let ptr = segment.as_mut_ptr() as *mut libc::c_void;
+        let mut server = Comm::create(ptr, len).unwrap();
+        assert_eq!(server.negotiate(), Err(CommError::NoHandshake));
+
+        let _client = Comm::attach(ptr, len).unwrap();
+        assert_eq!(server.negotiate(), Ok(CAPABILITIES));
+
+        segment[CLIENT_CAPABILITIES_OFFSET as usize] = CAP_COVERAGE | CAP_VALUES | 1 << 63;
+        assert_eq!(server.negotiate(), Ok(CAP_COVERAGE | CAP_VALUES));
+        assert!(!server.supports(CAP_PANIC_MESSAGES));
+        assert_eq!(server.panic_message(), "");
+
+        segment[CLIENT_VERSION_OFFSET as usize] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            server.negotiate(),
+            Err(CommError::VersionMismatch {
+                server: PROTOCOL_VERSION,
+                client: PROTOCOL_VERSION + 1
+            })
+        );
+        server.handshake_reset();
+        assert_eq!(server.client_version(), 0);
+    }
+
+    #[test]
+    fn comm_handshake_across_processes() {
+        let len = segment_size(MAX_BYTES);
+        let ptr = unsafe {
+            mmap(
+                ::std::ptr::null_mut(),
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED | MapFlags::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+            .unwrap()
+        };
+        let mut comm = Comm::create(ptr, len).unwrap();
+        let mut bytes = vec![0; MAX_BYTES];
+
+        match fork().unwrap() {
+            ForkResult::Child => {
+                client(&mut comm, &mut bytes);
+                unsafe { libc::_exit(0) }
+            }
+            ForkResult::Parent { child } => {
+                let mut waiter = Waiter::default();
+                let mut failed = 0;
+                for round in 0..ROUNDS {
+                    loop {
+                        match comm.client_status().unwrap() {
+                            ClientStatus::Ready => break,
+                            seen => comm.client_wait(&seen, &mut waiter, None),
+                        }
+                    }
+                    waiter.reset();
+                    comm.write_all(round_bytes(round, &mut bytes)).unwrap();
+                    comm.server_ready();
+                    loop {
+                        match comm.client_status().unwrap() {
+                            ClientStatus::Test(TestStatus::Passed) => break,
+                            ClientStatus::Test(_) => {
+                                failed += 1;
+                                break;
+                            }
+                            seen => comm.client_wait(&seen, &mut waiter, None),
+                        }
+                    }
+                    waiter.reset();
+                    comm.client_reset();
+                    comm.server_reset();
+                }
+                assert_eq!(waitpid(child, None), Ok(WaitStatus::Exited(child, 0)));
+                assert_eq!(failed, 0);
+                unsafe { munmap(ptr, len).unwrap() };
+            }
         }
     }
 }
@@ -0,0 +1,68 @@
+//! SanitizerCoverage trace-pc-guard callbacks
+//!
+//! Targets built by `cargo rqc build` are compiled with trace-pc-guard
+//! instrumentation, which calls into the functions below. Each guard is given
+//! a unique, non-zero id at startup and every time an edge is hit the counter
+//! for that id is bumped in the coverage region of the shared memory segment.
+//!
+//! NOTE -- the callbacks must not call into instrumented code, else they
+//! recurse. Keep them to raw loads and stores.
+
+use std::ptr;
+
+/// The size in bytes of the coverage region of the shared memory segment
+pub const COVERAGE_MAP_SIZE: usize = 65_536;
+
+static mut NEXT_GUARD: u32 = 0;
+static mut COVERAGE_MAP: *mut u8 = ptr::null_mut();
+
+/// Record coverage into `map` until [`disable`] is called
+///
+/// `map` must point to at least [`COVERAGE_MAP_SIZE`] writable bytes.
+pub(crate) unsafe fn enable(map: *mut u8) {
+    COVERAGE_MAP = map;
+}
+
+/// Stop recording coverage
+pub(crate) fn disable() {
+    unsafe {
+        COVERAGE_MAP = ptr::null_mut();
+    }
+}
+
+/// Assign every guard in `[start, stop)` a unique, non-zero id
+///
+/// # Safety
+///
+/// Called by instrumented code with the bounds of a module's guard section.
+#[no_mangle]
+pub unsafe extern "C" fn __sanitizer_cov_trace_pc_guard_init(start: *mut u32, stop: *mut u32) {
+    if start == stop || *start != 0 {
+        return;
+    }
+    let mut guard = start;
+    while guard < stop {
+        NEXT_GUARD += 1;
+        *guard = NEXT_GUARD;
+        guard = guard.add(1);
+    }
+}
+
+/// Bump the counter for the edge `guard` identifies
+///
+/// # Safety
+///
+/// Called by instrumented code with a guard previously passed to
+/// [`__sanitizer_cov_trace_pc_guard_init`].
+#[no_mangle]
+#[allow(clippy::implicit_saturating_add)]
+pub unsafe extern "C" fn __sanitizer_cov_trace_pc_guard(guard: *mut u32) {
+    let map = COVERAGE_MAP;
+    if map.is_null() || *guard == 0 {
+        return;
+    }
+    let counter = map.add(*guard as usize % COVERAGE_MAP_SIZE);
+    if *counter != u8::MAX {
+        *counter += 1;
+    }
+}
@@ -5,42 +5,105 @@ mod arbitrary;
 mod backoff;
 mod byte_buffer;
 mod comm;
+mod coverage;
+mod standalone;
+mod wait;
 
 pub use crate::arbitrary::*;
 pub use crate::backoff::*;
 pub use crate::byte_buffer::*;
 pub use crate::comm::*;
+pub use crate::coverage::*;
+pub use crate::standalone::*;
+pub use crate::wait::*;
 use nix::fcntl::OFlag;
 use nix::sys::mman::{mmap, shm_open, MapFlags, ProtFlags};
 use nix::sys::stat::{fstat, Mode};
+use std::cell::RefCell;
+use std::env;
 use std::io::Read;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::ptr;
 
-#[derive(Default)]
-pub struct RqcBuild {
-    byte_pool_capacity: Option<u32>,
+thread_local! {
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn record_panic(info: &PanicHookInfo) {
+    let payload = info.payload();
+    let message = match payload.downcast_ref::<&str>() {
+        Some(s) => (*s).to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    };
+    let message = match info.location() {
+        Some(location) => format!("{} at {}", message, location),
+        None => message,
+    };
+    LAST_PANIC.with(|p| *p.borrow_mut() = Some(message));
 }
 
+#[derive(Default)]
+pub struct RqcBuild {}
+
 impl RqcBuild {
     pub fn new() -> RqcBuild {
         RqcBuild::default()
     }
 
-    pub fn byte_pool_capacity(mut self, byte_pool_capacity: u32) -> RqcBuild {
-        self.byte_pool_capacity = Some(byte_pool_capacity);
+    /// Has no effect, the byte pool is now as large as whatever `cargo rqc
+    /// run` sends, see its `--maximum-test-bytes`
+    #[deprecated(note = "the byte pool is sized by `cargo rqc run --maximum-test-bytes`")]
+    pub fn byte_pool_capacity(self, _byte_pool_capacity: u32) -> RqcBuild {
         self
     }
 
     pub fn build(self) -> Rqc {
         Rqc {
-            byte_pool_capacity: self.byte_pool_capacity.unwrap_or(1_048_576) as usize,
+            properties: Vec::new(),
         }
     }
 }
 
 pub const TOTAL_BYTES: usize = 32_768;
 
+/// Generate the `main` of a target binary from property functions, each
+/// registered under its own name
+///
+/// ```ignore
+/// fn mul_is_add(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
+///     ...
+/// }
+///
+/// rqc_core::main!(mul_is_add);
+/// ```
+#[macro_export]
+macro_rules! main {
+    ($($property:ident),+ $(,)?) => {
+        fn main() {
+            $crate::RqcBuild::new()
+                .build()
+                $(.property(stringify!($property), $property))+
+                .main()
+        }
+    };
+}
+
+/// A test run against the bytes the server sends
+pub type Property = Box<dyn Fn(&mut FiniteByteBuffer) -> Result<TestResult, BufferOpError>>;
+
+/// The environment variable that, when set, has [`Rqc::run`] print the name of
+/// every registered property, one per line, and exit
+pub const LIST_PROPERTIES_ENV: &str = "RQC_LIST";
+
+/// The environment variable through which `cargo rqc run` names the shared
+/// memory of the target
+pub const SHM_PATH_ENV: &str = "RQC_SHM";
+
 pub struct Rqc {
-    byte_pool_capacity: usize,
+    properties: Vec<(String, Property)>,
 }
 
 pub enum TestResult {
@@ -50,23 +113,84 @@ pub enum TestResult {
 }
 
 impl Rqc {
-    pub fn run<F>(self, shm_path: &str, closure: F)
+    /// Register a property under `name`
+    ///
+    /// The server picks the property to run by name, running the first
+    /// registered if it names none.
+    pub fn property<F>(mut self, name: &str, property: F) -> Rqc
     where
-        F: Fn(&mut FiniteByteBuffer) -> Result<TestResult, BufferOpError>,
+        F: Fn(&mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> + 'static,
     {
-        let def_file_mode = Mode::S_IRUSR
-            | Mode::S_IWUSR
-            | Mode::S_IRGRP
-            | Mode::S_IWGRP
-            | Mode::S_IROTH
-            | Mode::S_IWOTH;
-        let memfd = shm_open(shm_path, OFlag::O_CREAT | OFlag::O_RDWR, def_file_mode)
-            .expect("failed to open shared memory");
+        assert!(name.len() <= PROPERTY_NAME_SIZE, "property name too long");
+        assert!(
+            self.properties.iter().all(|(n, _)| n != name),
+            "property {} registered twice",
+            name
+        );
+        self.properties.push((name.to_string(), Box::new(property)));
+        self
+    }
+
+    /// Run as the entry point of a target binary, see [`main!`]
+    ///
+    /// `cargo rqc run` hands the target the path of its shared memory in
+    /// `RQC_SHM`, or failing that as the only argument. Launched without one
+    /// the target runs its properties standalone instead.
+    pub fn main(self) {
+        let shm_path = env::var(SHM_PATH_ENV)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .or_else(|| env::args().nth(1));
+        match shm_path {
+            Some(shm_path) => self.run(&shm_path),
+            None => {
+                self.list_if_asked();
+                eprintln!(
+                    "no shared memory path given, running standalone; use `cargo rqc run` to fuzz"
+                );
+                self.standalone(STANDALONE_CASES)
+            }
+        }
+    }
+
+    /// Print the name of every property and exit if the server asks for it
+    fn list_if_asked(&self) {
+        if env::var_os(LIST_PROPERTIES_ENV).is_some() {
+            for (name, _) in &self.properties {
+                println!("{}", name);
+            }
+            ::std::process::exit(0);
+        }
+    }
+
+    pub fn run(self, shm_path: &str) {
+        self.list_if_asked();
+        assert!(!self.properties.is_empty(), "no properties registered");
+
+        // NOTE -- never O_CREAT: a segment that isn't there was not set up
+        // by a server, and making an empty one only hides that
+        let memfd = match shm_open(shm_path, OFlag::O_RDWR, Mode::empty()) {
+            Ok(memfd) => memfd,
+            Err(e) => {
+                eprintln!(
+                    "could not open shared memory {}: {}; targets are run by `cargo rqc run`",
+                    shm_path, e
+                );
+                ::std::process::exit(1);
+            }
+        };
         let total_bytes = fstat(memfd).expect("could not fstat shm file").st_size as usize;
+        if total_bytes < segment_size(0) {
+            eprintln!(
+                "shared memory {} is {} bytes, too small to have been set up by an rqc server; targets are run by `cargo rqc run`",
+                shm_path, total_bytes
+            );
+            ::std::process::exit(1);
+        }
 
         let ptr: *mut libc::c_void = unsafe {
             mmap(
-                0 as *mut libc::c_void,
+                ptr::null_mut(),
                 total_bytes,
                 ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
                 MapFlags::MAP_SHARED,
@@ -75,7 +199,33 @@ impl Rqc {
             )
             .expect("could not memory map shared memory file")
         };
-        let mut comm = Comm::new(ptr, total_bytes);
+        let mut comm = match Comm::attach(ptr, total_bytes) {
+            Ok(comm) => comm,
+            Err(e @ CommError::VersionMismatch { .. }) => {
+                eprintln!("could not attach to shared memory {}: {}", shm_path, e);
+                ::std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!(
+                    "could not attach to shared memory {}: {}; targets are run by `cargo rqc run`",
+                    shm_path, e
+                );
+                ::std::process::exit(1);
+            }
+        };
+
+        let name = comm.property();
+        let closure = if name.is_empty() {
+            &self.properties[0].1
+        } else {
+            match self.properties.iter().find(|(n, _)| *n == name) {
+                Some((_, property)) => property,
+                None => {
+                    eprintln!("no property named {}", name);
+                    ::std::process::exit(1);
+                }
+            }
+        };
 
         // NOTE(blt)
         //
@@ -86,51 +236,75 @@ impl Rqc {
         //  - switch states in the interpreter loop, this being either
         //    macro derived or user supplied
 
-        let mut byte_buf = Vec::with_capacity(self.byte_pool_capacity);
-        for _ in 0..self.byte_pool_capacity {
-            byte_buf.push(0);
-        }
+        // the server sizes the byte pool, see `--maximum-test-bytes`
+        let mut byte_buf = vec![0; comm.byte_pool_capacity()];
+
+        // Panics are reported back to the server rather than printed, see
+        // `record_panic`.
+        panic::set_hook(Box::new(record_panic));
 
-        let mut backoff = Backoff::default();
+        let mut waiter = Waiter::default();
         loop {
             comm.client_ready();
             loop {
                 match comm.server_status() {
-                    ServerStatus::Ready => {
-                        backoff.reset();
+                    Ok(ServerStatus::Ready) => {
+                        waiter.reset();
                         break;
                     }
-                    _ => backoff.delay(),
+                    Ok(seen) => comm.server_wait(&seen, &mut waiter, None),
+                    Err(e) => {
+                        eprintln!("could not read the server status: {}", e);
+                        ::std::process::exit(1);
+                    }
                 }
             }
 
-            match comm.read(&mut byte_buf) {
-                Err(_) => ::std::process::exit(0),
-                Ok(0) => continue,
-                Ok(_) => {}
-            }
-            let mut buf = FiniteByteBuffer::new(&byte_buf);
-            match closure(&mut buf) {
-                Ok(TestResult::Passed) => {
+            let total_bytes = match comm.read(&mut byte_buf) {
+                Err(e) => {
+                    eprintln!("could not read input from the server: {}", e);
+                    ::std::process::exit(1);
+                }
+                Ok(total_bytes) => total_bytes,
+            };
+            let mut buf = FiniteByteBuffer::new(&byte_buf[..total_bytes]);
+            comm.coverage_enable();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| closure(&mut buf)));
+            comm.coverage_disable();
+            match result {
+                Err(_) => {
+                    let message = LAST_PANIC
+                        .with(|p| p.borrow_mut().take())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    comm.client_panic_message(&message);
+                    comm.client_values(buf.values());
+                    comm.client_test_status(TestStatus::Panicked);
+                }
+                Ok(Ok(TestResult::Passed)) => {
                     comm.client_test_status(TestStatus::Passed);
                 }
-                Ok(TestResult::Skipped) => {
+                Ok(Ok(TestResult::Skipped)) => {
                     comm.client_test_status(TestStatus::Skipped);
                 }
-                Ok(TestResult::Failed) => {
+                Ok(Ok(TestResult::Failed)) => {
+                    comm.client_values(buf.values());
                     comm.client_test_status(TestStatus::Failed);
                 }
-                Err(BufferOpError::InsufficientBytes) => {
+                Ok(Err(BufferOpError::InsufficientBytes)) => {
                     comm.client_test_status(TestStatus::InsufficientBytes);
                 }
             }
             loop {
                 match comm.server_status() {
-                    ServerStatus::Default => {
-                        backoff.reset();
+                    Ok(ServerStatus::Default) => {
+                        waiter.reset();
                         break;
                     }
-                    _ => backoff.delay(),
+                    Ok(seen) => comm.server_wait(&seen, &mut waiter, None),
+                    Err(e) => {
+                        eprintln!("could not read the server status: {}", e);
+                        ::std::process::exit(1);
+                    }
                 }
             }
         }
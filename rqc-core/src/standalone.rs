@@ -0,0 +1,273 @@
+//! Running properties in-process, without a server
+//!
+//! No shared memory, no fork: each property is run against inputs made up by
+//! a seeded RNG. A target launched by hand, rather than by `cargo rqc run`,
+//! falls back to this, and the same property functions can be checked from
+//! `cargo test`:
+//!
+//! ```ignore
+//! #[test]
+//! fn mul_is_add_holds() {
+//!     rqc_core::Standalone::new().assert("mul_is_add", mul_is_add);
+//! }
+//! ```
+//!
+//! Every failure is reported with the seed of its run. Setting `RQC_SEED` to
+//! that seed reruns the same inputs.
+
+use crate::{record_panic, BufferOpError, FiniteByteBuffer, Rqc, TestResult, LAST_PANIC};
+use std::cell::Cell;
+use std::env;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::Once;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of inputs each property is run against standalone
+pub const STANDALONE_CASES: usize = 1_000;
+/// The size in bytes of each standalone input
+pub const STANDALONE_INPUT_SIZE: usize = 1_024;
+/// The environment variable that, when set, fixes the seed of standalone runs
+pub const STANDALONE_SEED_ENV: &str = "RQC_SEED";
+
+thread_local! {
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install a panic hook that records, rather than prints, panics raised
+/// inside a standalone run
+///
+/// Panics elsewhere go to the hook that was already installed, so that the
+/// test harness still reports its own failures.
+fn catch_panics() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(|c| c.get()) {
+                record_panic(info)
+            } else {
+                previous(info)
+            }
+        }));
+    });
+}
+
+/// xorshift64*, plenty for making up inputs and no extra dependency
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        // the state must never be zero
+        XorShift { state: seed | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let word = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+}
+
+/// A property failing in a standalone run
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandaloneFailure {
+    /// The seed of the run, reproducing it when given back
+    pub seed: u64,
+    /// The index of the failing case within the run
+    pub case: usize,
+    /// The panic message, if the property panicked rather than failed
+    pub panic_message: Option<String>,
+    /// The values the property generated for the failing case
+    pub values: Vec<String>,
+}
+
+impl fmt::Display for StandaloneFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.panic_message {
+            Some(ref message) => write!(f, "panicked: {}", message)?,
+            None => write!(f, "failed")?,
+        }
+        write!(
+            f,
+            " on case {} (seed {}, rerun with {}={})",
+            self.case, self.seed, STANDALONE_SEED_ENV, self.seed
+        )?;
+        for value in &self.values {
+            write!(f, "\n  {}", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs properties in-process against inputs from a seeded RNG
+pub struct Standalone {
+    cases: usize,
+    seed: u64,
+    input_size: usize,
+}
+
+impl Default for Standalone {
+    fn default() -> Standalone {
+        Standalone::new()
+    }
+}
+
+impl Standalone {
+    /// A run of [`STANDALONE_CASES`] cases, seeded from `RQC_SEED` if it is
+    /// set and from the clock otherwise
+    pub fn new() -> Standalone {
+        let seed = match env::var(STANDALONE_SEED_ENV) {
+            Ok(seed) => seed
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be a u64, was {}", STANDALONE_SEED_ENV, seed)),
+            Err(_) => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() ^ u64::from(d.subsec_nanos()))
+                .unwrap_or(0),
+        };
+        Standalone {
+            cases: STANDALONE_CASES,
+            seed,
+            input_size: STANDALONE_INPUT_SIZE,
+        }
+    }
+
+    pub fn cases(mut self, cases: usize) -> Standalone {
+        self.cases = cases;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Standalone {
+        self.seed = seed;
+        self
+    }
+
+    pub fn input_size(mut self, input_size: usize) -> Standalone {
+        self.input_size = input_size;
+        self
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Run `property` against every case, returning the first failure
+    ///
+    /// Each call starts the RNG over from the seed, so a property sees the
+    /// same inputs however many others the run checks before it.
+    pub fn check<F>(&self, property: F) -> Result<(), StandaloneFailure>
+    where
+        F: Fn(&mut FiniteByteBuffer) -> Result<TestResult, BufferOpError>,
+    {
+        catch_panics();
+        let mut rng = XorShift::new(self.seed);
+        let mut bytes = vec![0; self.input_size];
+        for case in 0..self.cases {
+            rng.fill(&mut bytes);
+            let mut buf = FiniteByteBuffer::new(&bytes);
+            CATCHING.with(|c| c.set(true));
+            let result = panic::catch_unwind(AssertUnwindSafe(|| property(&mut buf)));
+            CATCHING.with(|c| c.set(false));
+            let panic_message = match result {
+                Ok(Ok(TestResult::Passed))
+                | Ok(Ok(TestResult::Skipped))
+                | Ok(Err(BufferOpError::InsufficientBytes)) => continue,
+                Ok(Ok(TestResult::Failed)) => None,
+                Err(_) => Some(
+                    LAST_PANIC
+                        .with(|p| p.borrow_mut().take())
+                        .unwrap_or_else(|| "unknown panic".to_string()),
+                ),
+            };
+            return Err(StandaloneFailure {
+                seed: self.seed,
+                case,
+                panic_message,
+                values: buf.values().to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Run `property` against every case, panicking with the failure if it
+    /// has one, for use in `#[test]` functions
+    pub fn assert<F>(&self, name: &str, property: F)
+    where
+        F: Fn(&mut FiniteByteBuffer) -> Result<TestResult, BufferOpError>,
+    {
+        if let Err(failure) = self.check(property) {
+            panic!("{} {}", name, failure);
+        }
+    }
+}
+
+impl Rqc {
+    /// Run every property against `cases` random inputs, exiting with a
+    /// non-zero status at the first failure
+    pub fn standalone(self, cases: usize) {
+        let standalone = Standalone::new().cases(cases);
+        println!("running standalone with seed {}", standalone.get_seed());
+        for (name, property) in &self.properties {
+            if let Err(failure) = standalone.check(property) {
+                println!("{} {}", name, failure);
+                process::exit(1);
+            }
+            println!("{} passed {} cases", name, cases);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn small_sum(buf: &mut FiniteByteBuffer) -> Result<TestResult, BufferOpError> {
+        let lhs: u8 = buf.generate("lhs")?;
+        let rhs: u8 = buf.generate("rhs")?;
+        if lhs.checked_add(rhs).is_none() {
+            return Ok(TestResult::Failed);
+        }
+        Ok(TestResult::Passed)
+    }
+
+    #[test]
+    fn standalone_check_reports_and_reproduces() {
+        let passing = |_: &mut FiniteByteBuffer| Ok(TestResult::Passed);
+        assert_eq!(Standalone::new().cases(10).check(passing), Ok(()));
+
+        let failure = Standalone::new().seed(7).check(small_sum).unwrap_err();
+        assert_eq!(failure.seed, 7);
+        assert_eq!(failure.panic_message, None);
+        assert_eq!(failure.values.len(), 2);
+        assert_eq!(Standalone::new().seed(7).check(small_sum), Err(failure));
+    }
+
+    #[test]
+    fn standalone_check_catches_panics() {
+        let failure = Standalone::new()
+            .check(
+                |_: &mut FiniteByteBuffer| -> Result<TestResult, BufferOpError> { panic!("boom") },
+            )
+            .unwrap_err();
+        assert_eq!(failure.case, 0);
+        assert!(failure.panic_message.unwrap().starts_with("boom at "));
+    }
+
+    #[test]
+    #[should_panic(expected = "small_sum failed on case")]
+    fn standalone_assert_panics_on_failure() {
+        Standalone::new().assert("small_sum", small_sum);
+    }
+}
@@ -0,0 +1,145 @@
+//! Waiting on the status words shared between server and target
+//!
+//! A waiter spins briefly, for fast properties finish in microseconds, then
+//! sleeps on a futex until the other side changes the word and wakes it.
+//! Where futexes are not to be had it falls back to [`Backoff`].
+
+use crate::backoff::Backoff;
+use std::hint;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// The number of times a waiter spins before it sleeps
+const SPIN_LIMIT: u32 = 1_000;
+
+static FUTEX_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Sleep while `word` holds `seen`, for at most `timeout` if one is given
+///
+/// Returns early, and spuriously, as futexes do; callers re-check the word.
+#[cfg(target_os = "linux")]
+fn futex_wait(word: &AtomicU32, seen: u32, timeout: Option<Duration>) -> io::Result<()> {
+    let timespec = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    });
+    let timespec_ptr = match timespec {
+        Some(ref timespec) => timespec as *const libc::timespec,
+        None => ::std::ptr::null(),
+    };
+    // NOTE -- the word lives in memory shared between processes, so this
+    // must not be a FUTEX_PRIVATE_FLAG operation
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            seen,
+            timespec_ptr,
+        )
+    };
+    if res == -1 {
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EAGAIN) | Some(libc::EINTR) | Some(libc::ETIMEDOUT) => Ok(()),
+            _ => Err(err),
+        }
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn futex_wait(_: &AtomicU32, _: u32, _: Option<Duration>) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Wake every process sleeping on `word`
+#[cfg(target_os = "linux")]
+pub fn futex_wake(word: &AtomicU32) {
+    if FUTEX_UNSUPPORTED.load(Ordering::Relaxed) {
+        return;
+    }
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn futex_wake(_: &AtomicU32) {}
+
+/// Waits for a status word to change, spinning and then sleeping
+#[derive(Default)]
+pub struct Waiter {
+    spins: u32,
+    backoff: Backoff,
+}
+
+impl Waiter {
+    /// Forget past waits, to be called once the awaited status arrives
+    pub fn reset(&mut self) {
+        self.spins = 0;
+        self.backoff.reset();
+    }
+
+    /// Wait for `word` to change from `seen`, the value the caller last read,
+    /// for at most `timeout` if one is given
+    ///
+    /// May return before the word changes. Callers re-read the word and wait
+    /// again if it is not yet what they want.
+    pub fn wait(&mut self, word: &AtomicU32, seen: u32, timeout: Option<Duration>) {
+        if self.spins < SPIN_LIMIT {
+            self.spins += 1;
+            hint::spin_loop();
+            return;
+        }
+        if FUTEX_UNSUPPORTED.load(Ordering::Relaxed) {
+            self.backoff.delay();
+            return;
+        }
+        if futex_wait(word, seen, timeout).is_err() {
+            FUTEX_UNSUPPORTED.store(true, Ordering::Relaxed);
+            self.backoff.delay();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn wait_wakes_on_change() {
+        let word = Arc::new(AtomicU32::new(0));
+        let setter = {
+            let word = Arc::clone(&word);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                word.store(1, Ordering::Release);
+                futex_wake(&word);
+            })
+        };
+        let start = Instant::now();
+        let mut waiter = Waiter::default();
+        while word.load(Ordering::Acquire) == 0 {
+            waiter.wait(&word, 0, None);
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        setter.join().unwrap();
+    }
+
+    #[test]
+    fn wait_times_out() {
+        let word = AtomicU32::new(0);
+        let mut waiter = Waiter::default();
+        let start = Instant::now();
+        for _ in 0..SPIN_LIMIT + 1 {
+            waiter.wait(&word, 0, Some(Duration::from_millis(20)));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,226 @@
+//! `#[derive(Arbitrary)]` for rqc-core
+//!
+//! Structs are generated field by field, in declaration order. Enums first
+//! read one byte and take it modulo the number of variants to choose which
+//! variant to generate, then generate that variant's fields.
+//!
+//! A field can be generated by a function of your own in place of its
+//! `Arbitrary` implementation:
+//!
+//! ```ignore
+//! #[derive(Arbitrary)]
+//! struct Point {
+//!     #[arbitrary(with = "small_coordinate")]
+//!     x: u32,
+//!     y: u32,
+//! }
+//!
+//! fn small_coordinate<U: Unstructured + ?Sized>(u: &mut U) -> Result<u32, U::Error> {
+//!     Ok(u32::from(<u8 as Arbitrary>::arbitrary(u)?))
+//! }
+//! ```
+//!
+//! Every type parameter of the deriving type must itself be `Arbitrary`.
+//!
+//! `try_clone` is derived field by field, so that containers of the type can
+//! shrink, unless a field is generated by a function of your own: that
+//! field's type needn't be `Arbitrary`, and so can't be copied by it.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Field, Fields,
+    GenericParam, Generics, Ident, Lit, Meta, NestedMeta, Path, Result,
+};
+
+#[proc_macro_derive(Arbitrary, attributes(arbitrary))]
+pub fn derive_arbitrary(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let mut copies = Vec::new();
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            copies.push(copy(quote!(#name), &data.fields));
+            construct(quote!(#name), &data.fields)?
+        }
+        Data::Enum(ref data) => {
+            let count = data.variants.len();
+            if count == 0 {
+                return Err(Error::new_spanned(
+                    input,
+                    "cannot derive Arbitrary for an enum with no variants",
+                ));
+            }
+            let mut arms = Vec::with_capacity(count);
+            for (idx, variant) in data.variants.iter().enumerate() {
+                let idx = idx as u64;
+                let ident = &variant.ident;
+                let construct = construct(quote!(#name::#ident), &variant.fields)?;
+                arms.push(quote!(#idx => #construct));
+                copies.push(copy(quote!(#name::#ident), &variant.fields));
+            }
+            let count = count as u64;
+            quote! {
+                match u64::from(<u8 as ::rqc_core::Arbitrary>::arbitrary(u)?) % #count {
+                    #(#arms,)*
+                    _ => unreachable!(),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                input,
+                "cannot derive Arbitrary for a union",
+            ))
+        }
+    };
+
+    let try_clone = if has_custom_field(input) {
+        quote!()
+    } else {
+        quote! {
+            fn try_clone(&self) -> ::std::option::Option<Self> {
+                Some(match self {
+                    #(#copies,)*
+                })
+            }
+        }
+    };
+
+    let generics = add_bounds(input.generics.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rqc_core::Arbitrary for #name #ty_generics #where_clause {
+            fn arbitrary<RqcUnstructured: ::rqc_core::Unstructured + ?Sized>(
+                u: &mut RqcUnstructured,
+            ) -> ::std::result::Result<Self, RqcUnstructured::Error> {
+                Ok(#body)
+            }
+
+            #try_clone
+        }
+    })
+}
+
+/// Whether any field is generated by a function of its own
+fn has_custom_field(input: &DeriveInput) -> bool {
+    let fields: Vec<&Field> = match input.data {
+        Data::Struct(ref data) => data.fields.iter().collect(),
+        Data::Enum(ref data) => data.variants.iter().flat_map(|v| v.fields.iter()).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+    fields.iter().any(|field| {
+        field
+            .attrs
+            .iter()
+            .any(|attr| attr.path.is_ident("arbitrary"))
+    })
+}
+
+/// Require every type parameter be `Arbitrary`
+fn add_bounds(mut generics: Generics) -> Generics {
+    for param in &mut generics.params {
+        if let GenericParam::Type(ref mut param) = *param {
+            param.bounds.push(parse_quote!(::rqc_core::Arbitrary));
+        }
+    }
+    generics
+}
+
+/// Build the value at `path` from `fields`, generating each in turn
+fn construct(path: TokenStream2, fields: &Fields) -> Result<TokenStream2> {
+    Ok(match *fields {
+        Fields::Named(ref fields) => {
+            let mut inits = Vec::with_capacity(fields.named.len());
+            for field in &fields.named {
+                let ident = &field.ident;
+                let generate = generate(field)?;
+                inits.push(quote!(#ident: #generate));
+            }
+            quote!(#path { #(#inits,)* })
+        }
+        Fields::Unnamed(ref fields) => {
+            let mut inits = Vec::with_capacity(fields.unnamed.len());
+            for field in &fields.unnamed {
+                inits.push(generate(field)?);
+            }
+            quote!(#path ( #(#inits,)* ))
+        }
+        Fields::Unit => path,
+    })
+}
+
+/// The match arm copying the value at `path` out of `self`, field by field
+fn copy(path: TokenStream2, fields: &Fields) -> TokenStream2 {
+    match *fields {
+        Fields::Named(ref fields) => {
+            let idents: Vec<_> = fields.named.iter().map(|field| &field.ident).collect();
+            let (binds, copies) = (idents.clone(), idents.clone());
+            quote! {
+                #path { #(#binds,)* } => #path {
+                    #(#idents: ::rqc_core::Arbitrary::try_clone(#copies)?,)*
+                }
+            }
+        }
+        Fields::Unnamed(ref fields) => {
+            let binds: Vec<_> = (0..fields.unnamed.len())
+                .map(|idx| Ident::new(&format!("field{}", idx), Span::call_site()))
+                .collect();
+            let copies = binds.clone();
+            quote! {
+                #path ( #(#binds,)* ) => #path (
+                    #(::rqc_core::Arbitrary::try_clone(#copies)?,)*
+                )
+            }
+        }
+        Fields::Unit => quote!(#path => #path),
+    }
+}
+
+/// The expression generating `field`, by its custom function if one is given
+fn generate(field: &Field) -> Result<TokenStream2> {
+    let mut with = None;
+    for attr in &field.attrs {
+        if attr.path.is_ident("arbitrary") {
+            with = Some(parse_with(attr)?);
+        }
+    }
+    Ok(match with {
+        Some(with) => quote!(#with(u)?),
+        None => quote!(::rqc_core::Arbitrary::arbitrary(u)?),
+    })
+}
+
+/// Parse `#[arbitrary(with = "path::to::function")]`
+fn parse_with(attr: &Attribute) -> Result<Path> {
+    let expected = "expected `#[arbitrary(with = \"path::to::function\")]`";
+    let list = match attr.parse_meta()? {
+        Meta::List(list) => list,
+        meta => return Err(Error::new_spanned(meta, expected)),
+    };
+    if list.nested.len() != 1 {
+        return Err(Error::new_spanned(list, expected));
+    }
+    match list.nested[0] {
+        NestedMeta::Meta(Meta::NameValue(ref name_value)) if name_value.ident == "with" => {
+            match name_value.lit {
+                Lit::Str(ref function) => function.parse(),
+                ref lit => Err(Error::new_spanned(lit, expected)),
+            }
+        }
+        ref nested => Err(Error::new_spanned(nested, expected)),
+    }
+}
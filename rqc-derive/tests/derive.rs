@@ -0,0 +1,91 @@
+extern crate rqc_core;
+extern crate rqc_derive;
+
+use rqc_core::{Arbitrary, BufferOpError, FiniteByteBuffer, Unstructured};
+use rqc_derive::Arbitrary;
+
+#[derive(Arbitrary, Debug, PartialEq)]
+struct Named {
+    a: u8,
+    b: u16,
+}
+
+#[derive(Arbitrary, Debug, PartialEq)]
+struct Tuple(u8, bool);
+
+#[derive(Arbitrary, Debug, PartialEq)]
+struct Unit;
+
+#[derive(Arbitrary, Debug, PartialEq)]
+struct Wrapper<T> {
+    inner: T,
+}
+
+#[derive(Arbitrary, Debug, PartialEq)]
+enum Shape {
+    Empty,
+    Circle(u8),
+    Rect { w: u8, h: u8 },
+}
+
+#[derive(Arbitrary, Debug, PartialEq)]
+struct Custom {
+    #[arbitrary(with = "always_seven")]
+    seven: u8,
+    rest: u8,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Flag {
+    set: ::std::sync::atomic::AtomicBool,
+}
+
+fn always_seven<U: Unstructured + ?Sized>(_: &mut U) -> Result<u8, U::Error> {
+    Ok(7)
+}
+
+fn generate<T: Arbitrary>(bytes: &[u8]) -> Result<T, BufferOpError> {
+    T::arbitrary(&mut FiniteByteBuffer::new(bytes))
+}
+
+#[test]
+fn derive_structs() {
+    assert_eq!(generate(&[1, 2, 3]), Ok(Named { a: 1, b: 0x0302 }));
+    assert_eq!(generate(&[4, 1]), Ok(Tuple(4, true)));
+    assert_eq!(generate(&[]), Ok(Unit));
+    assert_eq!(generate(&[9]), Ok(Wrapper { inner: 9u8 }));
+    assert_eq!(
+        generate::<Named>(&[1, 2]),
+        Err(BufferOpError::InsufficientBytes)
+    );
+}
+
+#[test]
+fn derive_enum_variant_is_byte_modulo_count() {
+    assert_eq!(generate(&[0]), Ok(Shape::Empty));
+    assert_eq!(generate(&[4, 5]), Ok(Shape::Circle(5)));
+    assert_eq!(generate(&[254, 6, 7]), Ok(Shape::Rect { w: 6, h: 7 }));
+}
+
+#[test]
+fn derive_custom_field_function() {
+    assert_eq!(generate(&[1]), Ok(Custom { seven: 7, rest: 1 }));
+}
+
+#[test]
+fn derive_try_clone_by_field() {
+    assert_eq!(Named { a: 1, b: 2 }.try_clone(), Some(Named { a: 1, b: 2 }));
+    assert_eq!(Tuple(4, true).try_clone(), Some(Tuple(4, true)));
+    assert_eq!(
+        Shape::Rect { w: 6, h: 7 }.try_clone(),
+        Some(Shape::Rect { w: 6, h: 7 })
+    );
+    assert!(Flag {
+        set: Default::default()
+    }
+    .try_clone()
+    .is_none());
+    assert_eq!(Custom { seven: 7, rest: 1 }.try_clone(), None);
+    let shrunk: Vec<Vec<Tuple>> = vec![Tuple(4, true)].shrink().collect();
+    assert_eq!(shrunk, vec![vec![]]);
+}
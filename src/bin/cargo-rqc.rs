@@ -1,9 +1,13 @@
 extern crate clap;
 extern crate rqc;
+extern crate rqc_core;
 
 use clap::{App, AppSettings, Arg, SubCommand};
-use rqc::{Rqc, RqcBuilder};
+use rqc::{list_properties, resolve_target, Mutation, Outcome, Rqc, RqcBuilder};
+use rqc_core::{segment_size, TestStatus};
+use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
 fn main() {
     let app = App::new("cargo-rqc")
@@ -13,7 +17,7 @@ fn main() {
         .setting(AppSettings::GlobalVersion)
         .subcommand(
             SubCommand::with_name("build")
-                .about("Build all test targets")
+                .about("Build all test targets, recording those that link rqc-core by name")
                 .before_help("TODO")
                 .after_help("TODO"),
         )
@@ -25,7 +29,7 @@ fn main() {
                         .required(true)
                         .index(1)
                         .value_name("TARGET")
-                        .help("path to the test target")
+                        .help("path or name of the test target")
                         .takes_value(true),
                 )
                 .arg(
@@ -41,11 +45,136 @@ fn main() {
                         .long("shm-path")
                         .value_name("SHM_PATH")
                         .default_value("/RQC")
-                        .help("the shared memory file to be used to communicate between client and server")
+                        .help("the prefix of the shared memory files used to communicate between client and server, made unique to each run")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("crash-dir")
+                        .long("crash-dir")
+                        .value_name("CRASH_DIR")
+                        .default_value("rqc/crashes")
+                        .help("the directory failing inputs are saved to")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-shrink-attempts")
+                        .long("max-shrink-attempts")
+                        .value_name("MAX_SHRINK_ATTEMPTS")
+                        .default_value("1000")
+                        .help("the maximum number of candidates tried when shrinking a failing input, 0 to disable shrinking")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("mutations")
+                        .long("mutations")
+                        .value_name("MUTATIONS")
+                        .default_value("bit-flip,byte-flip,interesting-value,arithmetic,insert-block,delete-block,duplicate-block,splice")
+                        .help("comma separated list of the mutations applied to corpus entries")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("corpus")
+                        .long("corpus")
+                        .value_name("CORPUS_DIR")
+                        .help("directory of inputs run before any others, newly interesting inputs are saved into it")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("seed for the input generator, random if not given")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("hang-dir")
+                        .long("hang-dir")
+                        .value_name("HANG_DIR")
+                        .default_value("rqc/hangs")
+                        .help("the directory inputs that time out are saved to")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("timeout")
+                        .long("timeout")
+                        .value_name("MILLISECONDS")
+                        .default_value("1000")
+                        .help("the time the test target is given to run a single input before it is killed")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .value_name("JOBS")
+                        .default_value("1")
+                        .help("the number of test target processes run in parallel, each with its own shared memory file suffixed by its job number")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("property")
+                        .long("property")
+                        .value_name("NAME")
+                        .help("the property of the test target to run, its first if not given")
                         .takes_value(true),
                 )
                 .before_help("TODO")
                 .after_help("TODO"),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Re-run a saved input against a test target")
+                .arg(
+                    Arg::with_name("target")
+                        .required(true)
+                        .index(1)
+                        .value_name("TARGET")
+                        .help("path or name of the test target")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("input")
+                        .required(true)
+                        .index(2)
+                        .value_name("INPUT")
+                        .help("path to the file of bytes to give the test target")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("timeout")
+                        .long("timeout")
+                        .value_name("MILLISECONDS")
+                        .default_value("1000")
+                        .help("the time the test target is given to run a single input before it is killed")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("shm-path")
+                        .long("shm-path")
+                        .value_name("SHM_PATH")
+                        .default_value("/RQC")
+                        .help("the prefix of the shared memory files used to communicate between client and server, made unique to each run")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("property")
+                        .long("property")
+                        .value_name("NAME")
+                        .help("the property of the test target to run, its first if not given")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List the properties of a test target")
+                .arg(
+                    Arg::with_name("target")
+                        .required(true)
+                        .index(1)
+                        .value_name("TARGET")
+                        .help("path or name of the test target")
+                        .takes_value(true),
+                ),
         );
     let args = app.get_matches();
 
@@ -56,26 +185,140 @@ fn main() {
         }
         ("run", matches) => {
             let matches = matches.expect("could not even with matches");
-            let target = PathBuf::from(matches.value_of("target").expect("must supply a target"));
-            if !target.exists() {
-                panic!("given target does not exist on disk");
-            }
+            let target = target(matches.value_of("target").expect("must supply a target"));
             let max_test_bytes: usize = matches
                 .value_of("maximum-test-bytes")
                 .unwrap()
                 .parse()
                 .unwrap();
             let shm_path: String = String::from(matches.value_of("shm-path").unwrap());
+            let crash_dir = PathBuf::from(matches.value_of("crash-dir").unwrap());
+            let max_shrink_attempts: usize = matches
+                .value_of("max-shrink-attempts")
+                .unwrap()
+                .parse()
+                .unwrap();
+            let corpus_dir = matches.value_of("corpus").map(PathBuf::from);
+            let hang_dir = PathBuf::from(matches.value_of("hang-dir").unwrap());
+            let timeout = parse_timeout(matches.value_of("timeout").unwrap());
+            let property = matches.value_of("property").map(String::from);
+            let jobs: usize = matches
+                .value_of("jobs")
+                .unwrap()
+                .parse()
+                .expect("jobs must be a whole number");
+            if jobs == 0 {
+                panic!("jobs must be at least 1");
+            }
+            let seed: Option<u64> = matches
+                .value_of("seed")
+                .map(|s| s.parse().expect("seed must be an unsigned 64 bit integer"));
+            let mutations: Vec<Mutation> = matches
+                .value_of("mutations")
+                .unwrap()
+                .split(',')
+                .map(|m| m.trim().parse().unwrap_or_else(|e| panic!("{}", e)))
+                .collect();
 
-            let shm_total_bytes = max_test_bytes + 2048;
+            let shm_total_bytes = segment_size(max_test_bytes);
             let rqc: Rqc = RqcBuilder::default()
                 .shm_total_bytes(shm_total_bytes)
                 .shm_path(shm_path)
                 .target_byte_pool_size(max_test_bytes)
+                .crash_dir(crash_dir)
+                .max_shrink_attempts(max_shrink_attempts)
+                .mutations(mutations)
+                .corpus_dir(corpus_dir)
+                .seed(seed)
+                .hang_dir(hang_dir)
+                .timeout(timeout)
+                .jobs(jobs)
+                .property(property)
                 .build()
                 .unwrap();
             rqc.run(target.as_path())
         }
+        ("replay", matches) => {
+            let matches = matches.expect("could not even with matches");
+            let target = target(matches.value_of("target").expect("must supply a target"));
+            let input = PathBuf::from(matches.value_of("input").expect("must supply an input"));
+            let bytes = fs::read(&input).expect("could not read input");
+            let shm_path: String = String::from(matches.value_of("shm-path").unwrap());
+            let timeout = parse_timeout(matches.value_of("timeout").unwrap());
+            let property = matches.value_of("property").map(String::from);
+
+            let rqc: Rqc = RqcBuilder::default()
+                .shm_total_bytes(segment_size(bytes.len()))
+                .shm_path(shm_path)
+                .target_byte_pool_size(bytes.len())
+                .timeout(timeout)
+                .property(property)
+                .build()
+                .unwrap();
+            let (outcome, values) = rqc.replay(target.as_path(), &bytes);
+            let code = match outcome {
+                Outcome::Test(test_status) => {
+                    println!("target reported: {:?}", test_status);
+                    match test_status {
+                        TestStatus::Failed | TestStatus::Panicked => 1,
+                        _ => 0,
+                    }
+                }
+                Outcome::Exited(status) => {
+                    println!("target exited with status: {}", status);
+                    status
+                }
+                Outcome::Signaled(signal) => {
+                    println!("target died with {:?}", signal);
+                    1
+                }
+                Outcome::Panicked(message) => {
+                    println!("target panicked: {}", message);
+                    1
+                }
+                Outcome::TimedOut => {
+                    println!("target timed out");
+                    1
+                }
+            };
+            for value in &values {
+                println!("  {}", value);
+            }
+            ::std::process::exit(code);
+        }
+        ("list", matches) => {
+            let matches = matches.expect("could not even with matches");
+            let target = target(matches.value_of("target").expect("must supply a target"));
+            match list_properties(&target) {
+                Ok(properties) => {
+                    for property in properties {
+                        println!("{}", property);
+                    }
+                }
+                Err(e) => {
+                    println!("could not list the properties of target: {}", e);
+                    ::std::process::exit(1);
+                }
+            }
+        }
         (s, _) => panic!("unimplemented subcommand {}!", s),
     }
 }
+
+fn target(target: &str) -> PathBuf {
+    match resolve_target(target) {
+        Ok(path) => path,
+        Err(e) => {
+            println!("{}", e);
+            ::std::process::exit(1);
+        }
+    }
+}
+
+fn parse_timeout(millis: &str) -> Duration {
+    Duration::from_millis(
+        millis
+            .parse()
+            .expect("timeout must be a whole number of milliseconds"),
+    )
+}
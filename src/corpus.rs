@@ -0,0 +1,160 @@
+use crate::crash::fnv1a;
+use rand::Rng;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A collection of inputs that reached new coverage in the target
+///
+/// Edge hit counts are bucketed as in AFL, so an input is interesting if it
+/// hits an edge never seen before or hits a known edge a notably different
+/// number of times.
+///
+/// A corpus may be backed by a directory, which seeds the corpus and into
+/// which newly interesting inputs are saved, named by the hash of their
+/// contents.
+pub struct Corpus {
+    entries: Vec<Vec<u8>>,
+    seen: Vec<u8>,
+    edges: usize,
+    dir: Option<PathBuf>,
+}
+
+impl Corpus {
+    pub fn new(coverage_map_size: usize) -> Corpus {
+        Corpus {
+            entries: Vec::new(),
+            seen: vec![0; coverage_map_size],
+            edges: 0,
+            dir: None,
+        }
+    }
+
+    /// Back the corpus with the directory at `path`, creating it if need be
+    pub fn dir(mut self, path: &Path) -> io::Result<Corpus> {
+        fs::create_dir_all(path)?;
+        self.dir = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// Read every non-empty file in the corpus directory, in name order
+    pub fn seeds(&self) -> io::Result<Vec<Vec<u8>>> {
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        let mut seeds = Vec::with_capacity(paths.len());
+        for path in paths {
+            let bytes = fs::read(&path)?;
+            if !bytes.is_empty() {
+                seeds.push(bytes);
+            }
+        }
+        Ok(seeds)
+    }
+
+    /// Write `bytes` into the corpus directory, if there is one
+    pub fn save(&self, bytes: &[u8]) -> io::Result<()> {
+        if let Some(ref dir) = self.dir {
+            fs::write(dir.join(format!("{:016x}", fnv1a(bytes))), bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Keep `bytes` if the `coverage` it produced is new, returning whether it
+    /// was kept
+    pub fn observe(&mut self, bytes: &[u8], coverage: &[u8]) -> bool {
+        let mut interesting = false;
+        for (seen, count) in self.seen.iter_mut().zip(coverage.iter()) {
+            if *count == 0 {
+                continue;
+            }
+            let bucket = bucket(*count);
+            if *seen & bucket == 0 {
+                if *seen == 0 {
+                    self.edges += 1;
+                }
+                *seen |= bucket;
+                interesting = true;
+            }
+        }
+        if interesting {
+            self.entries.push(bytes.to_vec());
+        }
+        interesting
+    }
+
+    /// Pick an entry at random to derive a new input from
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> Option<&[u8]> {
+        if self.entries.is_empty() {
+            None
+        } else {
+            Some(&self.entries[rng.gen_range(0, self.entries.len())])
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The total number of distinct edges seen so far
+    pub fn edges(&self) -> usize {
+        self.edges
+    }
+}
+
+fn bucket(count: u8) -> u8 {
+    match count {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 4,
+        4..=7 => 8,
+        8..=15 => 16,
+        16..=31 => 32,
+        32..=127 => 64,
+        _ => 128,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn corpus_observe() {
+        let mut corpus = Corpus::new(4);
+        assert!(corpus.observe(&[1], &[0, 1, 0, 0]));
+        assert!(!corpus.observe(&[2], &[0, 1, 0, 0]));
+        assert!(corpus.observe(&[3], &[0, 2, 0, 0]));
+        assert!(!corpus.observe(&[4], &[0, 0, 0, 0]));
+        assert!(corpus.observe(&[5], &[1, 1, 0, 0]));
+        assert_eq!(corpus.len(), 3);
+        assert_eq!(corpus.edges(), 2);
+    }
+
+    #[test]
+    fn corpus_dir_round_trip() {
+        let path = ::std::env::temp_dir().join(format!("rqc-corpus-test-{}", ::std::process::id()));
+        let corpus = Corpus::new(4).dir(&path).unwrap();
+        fs::write(path.join("empty"), b"").unwrap();
+        corpus.save(&[2, 3]).unwrap();
+        corpus.save(&[1]).unwrap();
+        let mut seeds = corpus.seeds().unwrap();
+        seeds.sort();
+        assert_eq!(seeds, vec![vec![1], vec![2, 3]]);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use nix::sys::signal::Signal;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The manner in which a target failed a test case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Failure {
+    /// The target reported `TestStatus::Failed`
+    Failed,
+    /// The target exited with a non-zero status
+    Exited(i32),
+    /// The target was terminated by a signal
+    Signaled(Signal),
+    /// The target did not finish the test in time
+    TimedOut,
+    /// The test panicked
+    Panicked,
+}
+
+/// A classification of the signals a target may die from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrashKind {
+    /// SIGSEGV, an invalid memory reference
+    Segfault,
+    /// SIGABRT, as raised by a double panic or allocation failure
+    Abort,
+    /// SIGILL, an illegal instruction
+    IllegalInstruction,
+    /// SIGBUS, a misaligned or otherwise bad memory access
+    BusError,
+    /// SIGFPE, an erroneous arithmetic operation
+    FloatingPoint,
+    /// SIGKILL, most likely from the OOM killer
+    Killed,
+    /// Any other signal
+    Other,
+}
+
+/// Every [`CrashKind`], in the order they are reported
+pub const ALL_CRASH_KINDS: [CrashKind; 7] = [
+    CrashKind::Segfault,
+    CrashKind::Abort,
+    CrashKind::IllegalInstruction,
+    CrashKind::BusError,
+    CrashKind::FloatingPoint,
+    CrashKind::Killed,
+    CrashKind::Other,
+];
+
+impl CrashKind {
+    pub fn from_signal(signal: Signal) -> CrashKind {
+        match signal {
+            Signal::SIGSEGV => CrashKind::Segfault,
+            Signal::SIGABRT => CrashKind::Abort,
+            Signal::SIGILL => CrashKind::IllegalInstruction,
+            Signal::SIGBUS => CrashKind::BusError,
+            Signal::SIGFPE => CrashKind::FloatingPoint,
+            Signal::SIGKILL => CrashKind::Killed,
+            _ => CrashKind::Other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CrashKind::Segfault => "segfault",
+            CrashKind::Abort => "abort",
+            CrashKind::IllegalInstruction => "illegal_instruction",
+            CrashKind::BusError => "bus_error",
+            CrashKind::FloatingPoint => "floating_point",
+            CrashKind::Killed => "killed",
+            CrashKind::Other => "other",
+        }
+    }
+}
+
+impl Failure {
+    fn status(&self) -> &'static str {
+        match *self {
+            Failure::Failed => "failed",
+            Failure::Exited(_) => "exited",
+            Failure::Signaled(_) => "signaled",
+            Failure::TimedOut => "timed_out",
+            Failure::Panicked => "panicked",
+        }
+    }
+}
+
+/// Everything known about a failing input, as recorded in its sidecar
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub failure: Failure,
+    /// The property that failed, if not the target's first
+    pub property: Option<String>,
+    /// The seed of the run that found the failure
+    pub seed: u64,
+    /// The message and location of the panic, if the test panicked
+    pub panic_message: Option<String>,
+    /// The renderings of the values the test generated, if it reported any
+    pub values: Vec<String>,
+}
+
+/// What makes two failures the same bug: the manner of failure and, for a
+/// panic, where it was raised
+///
+/// The panic message itself is left out, `assert_eq!` puts the values that
+/// differed in it and every input would look like a new bug.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature {
+    failure: Failure,
+    location: Option<String>,
+}
+
+impl Report {
+    pub fn signature(&self) -> Signature {
+        // NOTE -- rqc-core records a panic as "<message> at <location>", a
+        // message without a location is the best key there is
+        let location = self
+            .panic_message
+            .as_ref()
+            .map(|message| match message.rfind(" at ") {
+                Some(at) => message[at + " at ".len()..].to_string(),
+                None => message.clone(),
+            });
+        Signature {
+            failure: self.failure,
+            location,
+        }
+    }
+}
+
+/// A directory of failing inputs
+///
+/// Every input is stored under the hash of its contents, next to a sidecar
+/// file with the same name plus a `.status` extension that records how the
+/// target failed and, one `value:` line apiece, the values the test generated.
+/// Shrunk reproducers get a `.min` extension.
+pub struct CrashDir {
+    path: PathBuf,
+}
+
+impl CrashDir {
+    /// Open the crash directory at `path`, creating it if need be
+    pub fn new(path: &Path) -> io::Result<CrashDir> {
+        fs::create_dir_all(path)?;
+        Ok(CrashDir {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Persist `bytes` and the `report` of how they failed, returning the
+    /// path of the saved input
+    pub fn save(&self, bytes: &[u8], report: &Report) -> io::Result<PathBuf> {
+        let failure = report.failure;
+        let name = format!("{:016x}", fnv1a(bytes));
+        let input = self.path.join(&name);
+        fs::write(&input, bytes)?;
+
+        let mut sidecar = fs::File::create(self.path.join(format!("{}.status", name)))?;
+        writeln!(sidecar, "status: {}", failure.status())?;
+        match report.property {
+            Some(ref property) => writeln!(sidecar, "property: {}", property)?,
+            None => writeln!(sidecar, "property: none")?,
+        }
+        match failure {
+            Failure::Signaled(signal) => writeln!(sidecar, "signal: {:?}", signal)?,
+            _ => writeln!(sidecar, "signal: none")?,
+        }
+        match failure {
+            Failure::Signaled(signal) => {
+                writeln!(sidecar, "kind: {}", CrashKind::from_signal(signal).name())?
+            }
+            _ => writeln!(sidecar, "kind: none")?,
+        }
+        match failure {
+            Failure::Exited(code) => writeln!(sidecar, "exit_code: {}", code)?,
+            _ => writeln!(sidecar, "exit_code: none")?,
+        }
+        writeln!(sidecar, "seed: {}", report.seed)?;
+        match report.panic_message {
+            Some(ref message) => writeln!(sidecar, "panic: {:?}", message)?,
+            None => writeln!(sidecar, "panic: none")?,
+        }
+        for value in &report.values {
+            writeln!(sidecar, "value: {}", value)?;
+        }
+        Ok(input)
+    }
+
+    /// Persist the shrunk form of the failing input previously saved at
+    /// `input`, returning the path it was saved to
+    pub fn save_shrunk(&self, input: &Path, bytes: &[u8]) -> io::Result<PathBuf> {
+        let shrunk = input.with_extension("min");
+        fs::write(&shrunk, bytes)?;
+        Ok(shrunk)
+    }
+}
+
+// NOTE -- FNV-1a, chosen only because it's stable across rust releases,
+// unlike the std hasher
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crash_dir_save() {
+        let path = ::std::env::temp_dir().join(format!("rqc-crash-test-{}", ::std::process::id()));
+        let dir = CrashDir::new(&path).unwrap();
+        let report = Report {
+            failure: Failure::Exited(101),
+            property: Some("mul_is_add".to_string()),
+            seed: 42,
+            panic_message: None,
+            values: vec!["lhs = 17".to_string(), "rhs = 200".to_string()],
+        };
+        let input = dir.save(&[1, 2, 3], &report).unwrap();
+        assert_eq!(fs::read(&input).unwrap(), vec![1, 2, 3]);
+        let status = fs::read_to_string(input.with_extension("status")).unwrap();
+        assert_eq!(
+            status,
+            "status: exited\nproperty: mul_is_add\nsignal: none\nkind: none\nexit_code: 101\nseed: 42\npanic: none\nvalue: lhs = 17\nvalue: rhs = 200\n"
+        );
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn signature_ignores_panic_message() {
+        let report = |message: &str| Report {
+            failure: Failure::Panicked,
+            property: None,
+            seed: 42,
+            panic_message: Some(message.to_string()),
+            values: Vec::new(),
+        };
+        let left =
+            report("assertion `left == right` failed\n  left: 1\n right: 2 at src/main.rs:9:5");
+        let right =
+            report("assertion `left == right` failed\n  left: 3\n right: 4 at src/main.rs:9:5");
+        let elsewhere =
+            report("assertion `left == right` failed\n  left: 1\n right: 2 at src/main.rs:12:5");
+        assert_eq!(left.signature(), right.signature());
+        assert_ne!(left.signature(), elsewhere.signature());
+    }
+}
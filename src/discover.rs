@@ -0,0 +1,256 @@
+//! Finding the test targets produced by a cargo build
+//!
+//! `cargo rqc build` reads cargo's `--message-format=json` output for the
+//! executables it produced and `cargo metadata` for which packages depend on
+//! rqc-core. Executables of those packages are test targets, recorded in a
+//! targets file so that `cargo rqc run` can take a target by name.
+
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Where `cargo rqc build` records the targets it found
+pub const TARGETS_FILE: &str = "rqc/targets";
+
+/// The target at `target`, if that is a path, or else the one named
+/// `target` by the last `cargo rqc build`
+pub fn resolve_target(target: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(target);
+    if path.exists() {
+        return Ok(path);
+    }
+    let index = TargetIndex::load(Path::new(TARGETS_FILE))
+        .map_err(|e| format!("could not read {}: {}", TARGETS_FILE, e))?;
+    match index.find(target) {
+        Some(path) => Ok(path.to_path_buf()),
+        None if index.is_empty() => Err(format!(
+            "no target at {} and no targets known, run `cargo rqc build` first",
+            target
+        )),
+        None => Err(format!(
+            "no target at {} and none by that name, known targets: {}",
+            target,
+            index.names().join(", ")
+        )),
+    }
+}
+
+/// An executable produced by a cargo build
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub name: String,
+    pub package_id: String,
+    /// Whether the executable is an example, which links dev-dependencies
+    pub example: bool,
+    pub executable: PathBuf,
+}
+
+/// Parse one line of `cargo build --message-format=json` output, returning
+/// the executable it announces, if any
+pub fn parse_artifact(line: &str) -> Option<Artifact> {
+    let message: Value = serde_json::from_str(line).ok()?;
+    if message["reason"] != "compiler-artifact" {
+        return None;
+    }
+    let kinds = message["target"]["kind"].as_array()?;
+    let example = kinds.iter().any(|k| k == "example");
+    if !example && !kinds.iter().any(|k| k == "bin") {
+        return None;
+    }
+    Some(Artifact {
+        name: message["target"]["name"].as_str()?.to_string(),
+        package_id: message["package_id"].as_str()?.to_string(),
+        example,
+        executable: PathBuf::from(message["executable"].as_str()?),
+    })
+}
+
+/// The human readable rendering of a compiler diagnostic in `cargo build
+/// --message-format=json` output, if the line is one
+pub fn rendered_message(line: &str) -> Option<String> {
+    let message: Value = serde_json::from_str(line).ok()?;
+    if message["reason"] != "compiler-message" {
+        return None;
+    }
+    message["message"]["rendered"].as_str().map(String::from)
+}
+
+/// The ids of the packages whose binaries link rqc-core, and of those whose
+/// examples do, from the output of `cargo metadata --format-version 1`
+pub fn linking_packages(metadata: &Value) -> (HashSet<String>, HashSet<String>) {
+    let mut bins: HashSet<String> = HashSet::new();
+    if let Some(packages) = metadata["packages"].as_array() {
+        for package in packages {
+            if package["name"] == "rqc-core" {
+                if let Some(id) = package["id"].as_str() {
+                    bins.insert(id.to_string());
+                }
+            }
+        }
+    }
+    let nodes = match metadata["resolve"]["nodes"].as_array() {
+        Some(nodes) => nodes,
+        None => return (bins, HashSet::new()),
+    };
+    let depends = |node: &Value, linking: &HashSet<String>, dev: bool| -> bool {
+        node["deps"].as_array().is_some_and(|deps| {
+            deps.iter().any(|dep| {
+                let linked = dep["pkg"].as_str().is_some_and(|pkg| linking.contains(pkg));
+                linked
+                    && dep["dep_kinds"].as_array().is_some_and(|kinds| {
+                        kinds
+                            .iter()
+                            .any(|k| k["kind"].is_null() || (dev && k["kind"] == "dev"))
+                    })
+            })
+        })
+    };
+
+    // NOTE -- normal dependencies are transitive, so keep sweeping the
+    // graph until no more packages are found to link rqc-core
+    loop {
+        let found: Vec<String> = nodes
+            .iter()
+            .filter(|node| depends(node, &bins, false))
+            .filter_map(|node| node["id"].as_str().map(String::from))
+            .filter(|id| !bins.contains(id))
+            .collect();
+        if found.is_empty() {
+            break;
+        }
+        bins.extend(found);
+    }
+    let examples = nodes
+        .iter()
+        .filter(|node| depends(node, &bins, true))
+        .filter_map(|node| node["id"].as_str().map(String::from))
+        .chain(bins.iter().cloned())
+        .collect();
+    (bins, examples)
+}
+
+/// The test targets found by the last `cargo rqc build`, by name
+///
+/// Stored one `name: path` line per target.
+#[derive(Debug, Default, PartialEq)]
+pub struct TargetIndex {
+    targets: Vec<(String, PathBuf)>,
+}
+
+impl TargetIndex {
+    /// Read the index at `path`, empty if there is none
+    pub fn load(path: &Path) -> io::Result<TargetIndex> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(TargetIndex::default()),
+            Err(e) => return Err(e),
+        };
+        let mut index = TargetIndex::default();
+        for line in contents.lines() {
+            if let Some(split) = line.find(": ") {
+                index.insert(&line[..split], PathBuf::from(&line[split + 2..]));
+            }
+        }
+        Ok(index)
+    }
+
+    /// Write the index to `path`, creating its directory if need be
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::File::create(path)?;
+        for (name, target) in &self.targets {
+            writeln!(file, "{}: {}", name, target.display())?;
+        }
+        Ok(())
+    }
+
+    /// Record the target `name` at `path`, replacing any of the same name
+    pub fn insert(&mut self, name: &str, path: PathBuf) {
+        self.targets.retain(|(n, _)| n != name);
+        self.targets.push((name.to_string(), path));
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Path> {
+        self.targets
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, path)| path.as_path())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.targets.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn discover_parse_artifact() {
+        let line = r#"{"reason":"compiler-artifact","package_id":"app 0.1.0","target":{"kind":["example"],"name":"mul_is_add"},"executable":"/t/examples/mul_is_add"}"#;
+        assert_eq!(
+            parse_artifact(line),
+            Some(Artifact {
+                name: "mul_is_add".to_string(),
+                package_id: "app 0.1.0".to_string(),
+                example: true,
+                executable: PathBuf::from("/t/examples/mul_is_add"),
+            })
+        );
+        let lib = r#"{"reason":"compiler-artifact","package_id":"app 0.1.0","target":{"kind":["lib"],"name":"app"},"executable":null}"#;
+        assert_eq!(parse_artifact(lib), None);
+        assert_eq!(parse_artifact(r#"{"reason":"build-finished"}"#), None);
+    }
+
+    #[test]
+    fn discover_linking_packages() {
+        let metadata: Value = serde_json::from_str(
+            r#"{
+                "packages": [{"name": "rqc-core", "id": "core"}, {"name": "app", "id": "app"},
+                             {"name": "dev", "id": "dev"}, {"name": "other", "id": "other"},
+                             {"name": "helper", "id": "helper"}],
+                "resolve": {"nodes": [
+                    {"id": "core", "deps": []},
+                    {"id": "helper", "deps": [{"pkg": "core", "dep_kinds": [{"kind": null}]}]},
+                    {"id": "app", "deps": [{"pkg": "helper", "dep_kinds": [{"kind": null}]}]},
+                    {"id": "dev", "deps": [{"pkg": "core", "dep_kinds": [{"kind": "dev"}]}]},
+                    {"id": "other", "deps": [{"pkg": "core", "dep_kinds": [{"kind": "build"}]}]}
+                ]}
+            }"#,
+        )
+        .unwrap();
+        let (bins, examples) = linking_packages(&metadata);
+        let mut bins: Vec<_> = bins.into_iter().collect();
+        bins.sort();
+        assert_eq!(bins, vec!["app", "core", "helper"]);
+        assert!(examples.contains("dev") && examples.contains("app"));
+        assert!(!examples.contains("other"));
+    }
+
+    #[test]
+    fn discover_target_index_round_trip() {
+        let path = ::std::env::temp_dir()
+            .join(format!("rqc-targets-test-{}", ::std::process::id()))
+            .join("targets");
+        assert!(TargetIndex::load(&path).unwrap().is_empty());
+        let mut index = TargetIndex::default();
+        index.insert("a", PathBuf::from("/t/a"));
+        index.insert("b", PathBuf::from("/t/b"));
+        index.insert("a", PathBuf::from("/t/a2"));
+        index.save(&path).unwrap();
+        let index = TargetIndex::load(&path).unwrap();
+        assert_eq!(index.names(), vec!["b", "a"]);
+        assert_eq!(index.find("a"), Some(Path::new("/t/a2")));
+        assert_eq!(index.find("c"), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! Stopping a run gracefully on SIGINT or SIGTERM
+//!
+//! The handler only records the signal. The run notices, stops its workers,
+//! which kill their targets and unlink their shared memory, and prints a
+//! summary on the way out. A second signal exits at once, unless it follows
+//! the first within `GRACE_NANOS`: `timeout` and a terminal both signal the
+//! whole process group, so one request to stop can arrive twice.
+
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use std::sync::atomic::{AtomicI32, AtomicI64, Ordering};
+
+/// How long after the first signal a repeat is taken as the same request
+const GRACE_NANOS: i64 = 1_000_000_000;
+
+static INTERRUPT: AtomicI32 = AtomicI32::new(0);
+/// The monotonic time in nanoseconds the first signal arrived at
+static INTERRUPTED_AT: AtomicI64 = AtomicI64::new(0);
+
+/// The monotonic clock in nanoseconds, safe to read in a signal handler
+fn now_nanos() -> i64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec * 1_000_000_000 + now.tv_nsec
+}
+
+extern "C" fn record_interrupt(signal: libc::c_int) {
+    let now = now_nanos();
+    if INTERRUPTED_AT
+        .compare_exchange(0, now, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        INTERRUPT.store(signal, Ordering::SeqCst);
+    } else if now - INTERRUPTED_AT.load(Ordering::SeqCst) >= GRACE_NANOS {
+        unsafe { libc::_exit(128 + signal) }
+    }
+}
+
+/// Catch SIGINT and SIGTERM from here on, see [`interrupted`]
+pub fn catch_interrupts() {
+    let action = SigAction::new(
+        SigHandler::Handler(record_interrupt),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    for signal in &[Signal::SIGINT, Signal::SIGTERM] {
+        unsafe { sigaction(*signal, &action) }.expect("could not install signal handler");
+    }
+}
+
+/// The signal asking the run to stop, if one has arrived
+pub fn interrupted() -> Option<Signal> {
+    match INTERRUPT.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Signal::from_c_int(signal).ok(),
+    }
+}
@@ -4,242 +4,589 @@ extern crate nix;
 extern crate rand;
 extern crate rqc_core;
 
+mod corpus;
+mod crash;
+mod discover;
+mod interrupt;
+mod mutate;
+mod shrink;
+mod stats;
+mod target;
+
+pub use crate::corpus::*;
+pub use crate::crash::*;
+pub use crate::discover::*;
+pub use crate::mutate::*;
+pub use crate::shrink::*;
+pub use crate::stats::*;
+pub use crate::target::*;
+
+use crate::interrupt::{catch_interrupts, interrupted};
 use derive_builder::Builder;
 use nix::errno::Errno;
-use nix::fcntl::OFlag;
-use nix::sys::mman::{mmap, shm_open, shm_unlink, MapFlags, ProtFlags};
-use nix::sys::signal::Signal;
-use nix::sys::stat::Mode;
-use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
-use nix::unistd::{execv, fork, ftruncate, ForkResult};
 use rand::rngs::SmallRng;
-use rand::{FromEntropy, Rng};
-use rqc_core::{Backoff, ClientStatus, Comm, ServerStatus, TestStatus};
-use std::ffi::CString;
-use std::io::Write;
-use std::path::Path;
+use rand::{Rng, SeedableRng};
+use rqc_core::{segment_size, TestStatus, COVERAGE_MAP_SIZE};
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
 use std::time::{Duration, Instant};
+use std::vec;
 
 #[derive(Builder, Debug)]
 pub struct Rqc {
+    #[builder(default = "segment_size(1024)")]
     shm_total_bytes: usize,
+    /// The prefix of the shared memory names, see `Rqc::shm_path`
+    #[builder(default = "\"/RQC\".to_string()")]
     shm_path: String,
+    #[builder(setter(skip), default = "run_id()")]
+    run_id: String,
+    #[builder(default = "1024")]
     target_byte_pool_size: usize,
+    #[builder(default = "PathBuf::from(\"rqc/crashes\")")]
+    crash_dir: PathBuf,
+    #[builder(default = "1_000")]
+    max_shrink_attempts: usize,
+    #[builder(default = "ALL_MUTATIONS.to_vec()")]
+    mutations: Vec<Mutation>,
+    #[builder(default = "None")]
+    corpus_dir: Option<PathBuf>,
+    #[builder(default = "None")]
+    seed: Option<u64>,
+    #[builder(default = "PathBuf::from(\"rqc/hangs\")")]
+    hang_dir: PathBuf,
+    #[builder(default = "Duration::from_secs(1)")]
+    timeout: Duration,
+    #[builder(default = "1")]
+    jobs: usize,
+    #[builder(default = "None")]
+    property: Option<String>,
+}
+
+/// State shared between the workers of a run
+struct Shared {
+    seed: u64,
+    crash_dir: CrashDir,
+    hang_dir: CrashDir,
+    corpus: Mutex<Corpus>,
+    seeds: Mutex<vec::IntoIter<Vec<u8>>>,
+    stats: Mutex<Stats>,
+    /// The failures already saved, only the first input to fail a given way
+    /// is kept
+    signatures: Mutex<HashSet<Signature>>,
+    /// If this is ever Some then every worker quits
+    exit_status: Mutex<Option<i32>>,
+}
+
+impl Shared {
+    fn running(&self) -> bool {
+        self.exit_status.lock().unwrap().is_none()
+    }
+
+    /// Stop the run, keeping the first exit status given
+    fn stop(&self, status: i32) {
+        let mut exit_status = self.exit_status.lock().unwrap();
+        if exit_status.is_none() {
+            *exit_status = Some(status);
+        }
+    }
 }
 
 impl Rqc {
-    pub fn build(&self) -> () {
+    pub fn build(&self) {
         let cargo_path = env!("CARGO");
 
         let mut rustflags: String = "-C debug-assertions \
                                      -C overflow_checks \
                                      -C opt-level=3 \
-                                     -C target-cpu=native"
+                                     -C target-cpu=native \
+                                     -C passes=sancov-module \
+                                     -C llvm-args=-sanitizer-coverage-level=3 \
+                                     -C llvm-args=-sanitizer-coverage-trace-pc-guard"
             .to_string();
 
         // add user provided flags
         let other_flags = ::std::env::var("RUSTFLAGS").unwrap_or_default();
         if !other_flags.is_empty() {
-            rustflags.push_str(" ");
+            rustflags.push(' ');
             rustflags.push_str(&other_flags);
         }
 
-        let mut cmd = ::std::process::Command::new(cargo_path);
-        cmd.arg("build").arg("--release");
+        let mut cmd = Command::new(cargo_path);
+        cmd.arg("build")
+            .arg("--release")
+            .arg("--bins")
+            .arg("--examples")
+            .arg("--message-format=json");
+        // NOTE -- RUSTFLAGS apply to build scripts and proc-macros unless
+        // we name a target explicitly, and those don't link rqc-core's
+        // coverage callbacks
+        cmd.arg("--target").arg(host_triple());
+
+        let mut child = cmd
+            .env("RUSTFLAGS", &rustflags)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let mut artifacts = Vec::new();
+        for line in BufReader::new(stdout).lines() {
+            let line = line.expect("could not read cargo output");
+            if let Some(rendered) = rendered_message(&line) {
+                eprint!("{}", rendered);
+            } else if let Some(artifact) = parse_artifact(&line) {
+                artifacts.push(artifact);
+            }
+        }
+        let status = child.wait().unwrap();
+        if !status.success() {
+            ::std::process::exit(status.code().unwrap_or(1));
+        }
 
-        let status = cmd.env("RUSTFLAGS", &rustflags).status().unwrap();
-        ::std::process::exit(status.code().unwrap_or(1));
+        let output = Command::new(cargo_path)
+            .arg("metadata")
+            .arg("--format-version")
+            .arg("1")
+            .output()
+            .unwrap();
+        let metadata =
+            serde_json::from_slice(&output.stdout).expect("could not parse cargo metadata");
+        let (bins, examples) = linking_packages(&metadata);
+        let mut index = TargetIndex::default();
+        for artifact in artifacts {
+            let linking = if artifact.example { &examples } else { &bins };
+            // a package linking rqc-core may build other binaries, cargo-rqc
+            // among them, so only those that list their properties count
+            let is_target = linking.contains(&artifact.package_id)
+                && list_properties(&artifact.executable)
+                    .is_ok_and(|properties| !properties.is_empty());
+            if is_target {
+                println!(
+                    "found target {} at {}",
+                    artifact.name,
+                    artifact.executable.display()
+                );
+                index.insert(&artifact.name, artifact.executable);
+            }
+        }
+        if index.is_empty() {
+            println!("no test targets linking rqc-core were built");
+        }
+        if let Err(e) = index.save(Path::new(TARGETS_FILE)) {
+            println!("could not save targets to {}: {}", TARGETS_FILE, e);
+            ::std::process::exit(1);
+        }
+        ::std::process::exit(0);
     }
 
-    pub fn run(&self, target: &Path) -> () {
+    pub fn run(&self, target: &Path) {
         assert!(self.target_byte_pool_size < self.shm_total_bytes);
+        assert!(self.jobs > 0);
+        self.check_property(target);
 
-        let def_file_mode = Mode::S_IRUSR
-            | Mode::S_IWUSR
-            | Mode::S_IRGRP
-            | Mode::S_IWGRP
-            | Mode::S_IROTH
-            | Mode::S_IWOTH;
-        let _ = shm_unlink(self.shm_path.as_str());
-        let memfd = shm_open(
-            self.shm_path.as_str(),
-            OFlag::O_CREAT | OFlag::O_RDWR,
-            def_file_mode,
-        )
-        .expect("failed to open shared memory");
-        if let Err(e) = ftruncate(memfd, self.shm_total_bytes as i64) {
-            shm_unlink(self.shm_path.as_str()).expect("failed to unlink opened shm");
-            println!(
-                "could not truncate shared memory to appropriate size: {}",
-                e
-            );
-            ::std::process::exit(1);
+        let seed = self.seed.unwrap_or_else(rand::random);
+        println!("seed: {}", seed);
+        let mut corpus = Corpus::new(COVERAGE_MAP_SIZE);
+        if let Some(ref corpus_dir) = self.corpus_dir {
+            corpus = corpus
+                .dir(corpus_dir)
+                .expect("could not create corpus directory");
         }
-        let ptr: *mut libc::c_void = unsafe {
-            mmap(
-                0 as *mut libc::c_void,
-                self.shm_total_bytes,
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
-                MapFlags::MAP_SHARED,
-                memfd,
-                0,
-            )
-            .expect("could not memory map shared memory file")
+        let seeds = corpus.seeds().expect("could not read corpus directory");
+        println!("loaded {} corpus inputs", seeds.len());
+
+        let shared = Shared {
+            seed,
+            crash_dir: CrashDir::new(&self.crash_dir).expect("could not create crash directory"),
+            hang_dir: CrashDir::new(&self.hang_dir).expect("could not create hang directory"),
+            corpus: Mutex::new(corpus),
+            seeds: Mutex::new(seeds.into_iter()),
+            stats: Mutex::new(Stats::default()),
+            signatures: Mutex::new(HashSet::new()),
+            exit_status: Mutex::new(None),
         };
-        let mut comm = Comm::new(ptr, self.shm_total_bytes);
-        // NOTE(blt) -- okay, now, at this point we actually need to fork/exec
-        // and get a child process to do a similar mmap dance. Also, we should
-        // hide this inside a proper type. Initial goal: just get a handshake
-        // going between the client (test target) and server (test runner).
-
-        let c_path = CString::new(
-            target
-                .as_os_str()
-                .to_str()
-                .expect("path contains invalid unicode characters"),
-        )
-        .expect("unable to coerce path into c-style string");
-
-        let mut restarts = 0;
-        let mut passed = 0;
-        let mut skipped = 0;
-        let mut failed = 0;
-        let mut crash_failure = 0;
-        let mut insufficient_bytes = 0;
-        let mut test_cases = 0;
 
         let ui_delay = Duration::from_secs(1);
-        let mut start = Instant::now();
+        let started = Instant::now();
+        let mut start = started;
+        catch_interrupts();
 
-        let mut restart_target = false;
-        let mut exit_status = None; // If exit status is ever Some then we quit
-        loop {
-            if exit_status.is_some() {
-                break;
-            }
-            match fork() {
-                Ok(ForkResult::Parent { child, .. }) => {
-                    let mut rng = SmallRng::from_entropy();
-                    let mut bytes: Vec<u8> = Vec::with_capacity(self.target_byte_pool_size);
-                    for _ in 0..self.target_byte_pool_size {
-                        bytes.push(0);
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.jobs)
+                .map(|job| {
+                    let shared = &shared;
+                    scope.spawn(move || self.work(job, target, shared))
+                })
+                .collect();
+
+            while shared.running() {
+                // NOTE -- a worker only returns once the run is over, so
+                // one that has returned without saying so has panicked
+                if workers.iter().any(|worker| worker.is_finished()) {
+                    shared.stop(1);
+                    break;
+                }
+                if let Some(signal) = interrupted() {
+                    shared.stop(128 + signal as i32);
+                    let _ = writeln!(io::stdout(), "caught {:?}, stopping", signal);
+                    break;
+                }
+                if start.elapsed() >= ui_delay {
+                    start = Instant::now();
+                    let stats = shared.stats.lock().unwrap();
+                    let corpus = shared.corpus.lock().unwrap();
+                    // NOTE -- `println!` panics once stdout's reader is gone,
+                    // as when the signal that stops us also stops a `| tee`,
+                    // and a panic here would leave the scope waiting on
+                    // workers nobody stopped
+                    let printed = writeln!(
+                        io::stdout(),
+                        "{} Corpus: {} Edges: {}",
+                        *stats,
+                        corpus.len(),
+                        corpus.edges()
+                    );
+                    if printed.is_err() {
+                        shared.stop(1);
+                        break;
                     }
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        // every worker has returned, dropping its target, so no target is
+        // left running and no shared memory is left linked
+        let stats = shared.stats.lock().unwrap();
+        let corpus = shared.corpus.lock().unwrap();
+        let _ = writeln!(
+            io::stdout(),
+            "stopped after {:?}: {} Corpus: {} Edges: {} Failures: {}",
+            started.elapsed(),
+            *stats,
+            corpus.len(),
+            corpus.edges(),
+            shared.signatures.lock().unwrap().len()
+        );
+        let _ = io::stdout().flush();
+        let exit_status = shared.exit_status.lock().unwrap().unwrap();
+        ::std::process::exit(exit_status);
+    }
 
-                    let mut backoff = Backoff::default();
-                    loop {
-                        backoff.delay();
-                        if start.elapsed() >= ui_delay {
-                            start = Instant::now();
-                            println!(
-                                "TestCases: {} Restarts: {} Passed: {} Skipped: {} Failed: {} InsufficientBytes: {} CrashFail: {}",
-                                test_cases, restarts, passed, skipped, failed, insufficient_bytes, crash_failure
-                            );
+    /// The names of the properties `target` registers
+    pub fn list(&self, target: &Path) -> Vec<String> {
+        match list_properties(target) {
+            Ok(properties) => properties,
+            Err(e) => {
+                println!("could not list the properties of target: {}", e);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    /// Exit if `target` has no property by the name we've been asked to run
+    fn check_property(&self, target: &Path) {
+        if let Some(ref property) = self.property {
+            let properties = self.list(target);
+            if !properties.contains(property) {
+                println!(
+                    "target has no property named {}, it has: {}",
+                    property,
+                    properties.join(", ")
+                );
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    /// Start the target for worker `job`, running the chosen property
+    fn target(&self, target: &Path, job: usize) -> Target {
+        let target =
+            Target::new(target, &self.shm_path(job), self.shm_total_bytes).timeout(self.timeout);
+        match self.property {
+            Some(ref property) => target.property(property),
+            None => target,
+        }
+    }
+
+    /// The shared memory path of the target driven by worker `job`
+    ///
+    /// Paths carry the run's id, so concurrent runs on one host never share
+    /// a segment.
+    fn shm_path(&self, job: usize) -> String {
+        format!("{}-{}-{}", self.shm_path, self.run_id, job)
+    }
+
+    /// Drive one target process until the run is stopped
+    ///
+    /// Every worker draws on and feeds the one corpus, but has its own target,
+    /// shared memory segment and random number generator. The generator of
+    /// worker `job` is seeded with the run seed plus `job`.
+    fn work(&self, job: usize, target: &Path, shared: &Shared) {
+        let mut target = self.target(target, job);
+        let seed = shared.seed;
+        let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(job as u64));
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.target_byte_pool_size);
+        let mutator = Mutator::new(self.target_byte_pool_size).mutations(&self.mutations);
+        let mut test_cases = 0;
+
+        while shared.running() {
+            // Run every seed before generating anything, then mostly derive new
+            // inputs from those that found new coverage, falling back to pure
+            // random bytes now and then.
+            bytes.clear();
+            let seed_input = shared.seeds.lock().unwrap().next();
+            let is_seed = seed_input.is_some();
+            {
+                let corpus = shared.corpus.lock().unwrap();
+                match (seed_input, corpus.choose(&mut rng)) {
+                    (Some(seed_input), _) => {
+                        bytes.extend_from_slice(&seed_input);
+                        bytes.truncate(self.target_byte_pool_size);
+                    }
+                    (None, Some(entry)) if !rng.gen_ratio(1, 8) => {
+                        bytes.extend_from_slice(entry);
+                        let other = corpus.choose(&mut rng);
+                        mutator.mutate(&mut rng, &mut bytes, other);
+                    }
+                    _ => {
+                        for _ in 0..self.target_byte_pool_size {
+                            bytes.push(rng.gen::<u8>());
                         }
-                        // TODO(blt) -- if the child dies we need to be sure not
-                        // to leave an orphan around
-                        match waitpid(child, Some(WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED)) {
-                            Ok(status) => match status {
-                                WaitStatus::StillAlive => {
-                                    match comm.client_status() {
-                                        ClientStatus::Default => {
-                                            // nothing to do
-                                        }
-                                        ClientStatus::Ready => match comm.server_status() {
-                                            ServerStatus::Default => {
-                                                backoff.reset();
-                                                for b in bytes.iter_mut() {
-                                                    *b = rng.gen::<u8>();
-                                                }
-                                                let _ = comm.write(&mut bytes).expect(
-                                                    "unable to write random bytes to target",
-                                                );
-                                                test_cases += 1;
-                                                comm.server_ready();
-                                            }
-                                            ServerStatus::Ready => {}
-                                        },
-                                        ClientStatus::Test(test_status) => {
-                                            match test_status {
-                                                TestStatus::Passed => passed += 1,
-                                                TestStatus::Skipped => skipped += 1,
-                                                TestStatus::Failed => failed += 1,
-                                                TestStatus::InsufficientBytes => {
-                                                    insufficient_bytes += 1
-                                                }
-                                            }
-                                            comm.client_reset();
-                                            comm.server_reset();
-                                        }
-                                    }
-                                }
-                                WaitStatus::Exited(_, status) => {
-                                    if status != 0 {
-                                        println!("target exited with non-zero status: {}", status);
-                                        crash_failure += 1;
-                                        restart_target = true;
-                                    } else {
-                                        if test_cases == 0 {
-                                            println!(
-                                                "target exited before a test could be given to it"
-                                            );
-                                            exit_status = Some(1); // TODO(blt) -- have well-defined exit status meanings
-                                        } else {
-                                            restart_target = true;
-                                        }
-                                    }
-                                }
-                                WaitStatus::Signaled(_, signal, _) => match signal {
-                                    Signal::SIGSEGV => {
-                                        println!("target died with SIGSEGV");
-                                        restart_target = true;
-                                    }
-                                    _ => unreachable!(),
+                    }
+                }
+            }
+            test_cases += 1;
+            shared.stats.lock().unwrap().test_cases += 1;
+            let outcome = target.exec(&bytes);
+            let kept = match outcome {
+                Ok(Outcome::Test(TestStatus::Failed)) => false,
+                Ok(Outcome::Test(_)) => shared
+                    .corpus
+                    .lock()
+                    .unwrap()
+                    .observe(&bytes, target.coverage()),
+                _ => false,
+            };
+            if kept && !is_seed {
+                if let Err(e) = shared.corpus.lock().unwrap().save(&bytes) {
+                    println!("could not save input to corpus: {}", e);
+                }
+            }
+            match outcome {
+                Ok(Outcome::Test(test_status)) => {
+                    let mut stats = shared.stats.lock().unwrap();
+                    match test_status {
+                        TestStatus::Passed => stats.passed += 1,
+                        TestStatus::Skipped => stats.skipped += 1,
+                        TestStatus::Failed => {
+                            stats.failed += 1;
+                            drop(stats);
+                            let values = target.values();
+                            self.save_failure(
+                                &mut target,
+                                shared,
+                                &bytes,
+                                Report {
+                                    failure: Failure::Failed,
+                                    property: self.property.clone(),
+                                    seed,
+                                    panic_message: None,
+                                    values,
                                 },
-                                s => {
-                                    println!("target finished with status: {:?}", s);
-                                }
-                            },
-                            Err(e) => match e.as_errno() {
-                                Some(Errno::ECHILD) => {}
-                                _ => {
-                                    println!("waiting on target failed with: {}", e);
-                                    exit_status = Some(1) // TODO(blt) -- have well-defined exit status meanings
-                                }
-                            },
+                            );
                         }
-                        if restart_target || exit_status.is_some() {
-                            // must wait for the SIGCHLD or we get a zombie process
-                            match waitpid(child, None) {
-                                Ok(status) => match status {
-                                    WaitStatus::Signaled(_, Signal::SIGCHLD, _) => {}
-                                    _ => unreachable!(),
-                                },
-                                _ => unreachable!(),
-                            }
-
-                            restarts += 1;
-                            break;
+                        TestStatus::InsufficientBytes => stats.insufficient_bytes += 1,
+                        // `Target::exec` reports panics with their message,
+                        // should one come through bare still count it
+                        TestStatus::Panicked => stats.panicked += 1,
+                    }
+                }
+                Ok(Outcome::Panicked(message)) => {
+                    shared.stats.lock().unwrap().panicked += 1;
+                    let values = target.values();
+                    self.save_failure(
+                        &mut target,
+                        shared,
+                        &bytes,
+                        Report {
+                            failure: Failure::Panicked,
+                            property: self.property.clone(),
+                            seed,
+                            panic_message: Some(message),
+                            values,
+                        },
+                    );
+                }
+                Ok(Outcome::Exited(status)) => {
+                    if status != 0 {
+                        println!("target exited with non-zero status: {}", status);
+                        shared.stats.lock().unwrap().crash_failure += 1;
+                        self.save_failure(
+                            &mut target,
+                            shared,
+                            &bytes,
+                            Report {
+                                failure: Failure::Exited(status),
+                                property: self.property.clone(),
+                                seed,
+                                panic_message: None,
+                                values: Vec::new(),
+                            },
+                        );
+                        shared.stats.lock().unwrap().restarts += 1;
+                    } else if test_cases == 1 {
+                        println!("target exited before a test could be given to it");
+                        shared.stop(1); // TODO(blt) -- have well-defined exit status meanings
+                    } else {
+                        shared.stats.lock().unwrap().restarts += 1;
+                    }
+                }
+                Ok(Outcome::Signaled(signal)) => {
+                    let kind = CrashKind::from_signal(signal);
+                    println!("target died with {:?} ({})", signal, kind.name());
+                    shared.stats.lock().unwrap().signal(kind);
+                    self.save_failure(
+                        &mut target,
+                        shared,
+                        &bytes,
+                        Report {
+                            failure: Failure::Signaled(signal),
+                            property: self.property.clone(),
+                            seed,
+                            panic_message: None,
+                            values: Vec::new(),
+                        },
+                    );
+                    shared.stats.lock().unwrap().restarts += 1;
+                }
+                Ok(Outcome::TimedOut) => {
+                    println!("target timed out after {:?}", self.timeout);
+                    shared.stats.lock().unwrap().hangs += 1;
+                    let report = Report {
+                        failure: Failure::TimedOut,
+                        property: self.property.clone(),
+                        seed,
+                        panic_message: None,
+                        values: Vec::new(),
+                    };
+                    match shared.hang_dir.save(&bytes, &report) {
+                        Ok(path) => {
+                            println!("saved hanging input to {} (seed {})", path.display(), seed)
                         }
+                        Err(e) => println!("could not save hanging input: {}", e),
                     }
+                    shared.stats.lock().unwrap().restarts += 1;
                 }
-                Ok(ForkResult::Child) => {
-                    // TODO(blt) for some reason the args aren't getting passed to the child
-                    execv(
-                        &c_path,
-                        &[
-                            c_path.clone(),
-                            CString::new(self.shm_path.as_str()).unwrap(),
-                        ],
-                    )
-                    .expect("could not execv");
+                Err(TargetError::Wait(ref e)) if e.as_errno() == Some(Errno::ECHILD) => {}
+                Err(e) => {
+                    println!("{}", e);
+                    shared.stop(1); // TODO(blt) -- have well-defined exit status meanings
                 }
-                Err(_) => {
-                    println!("Unable to fork target");
-                    ::std::process::exit(1);
+            }
+        }
+    }
+
+    /// Persist a failing input, then shrink it against the target and
+    /// persist the minimal reproducer alongside
+    ///
+    /// Only the first input to fail in any given way, see [`Signature`], is
+    /// kept and shrunk, so a bug that's easy to hit doesn't fill the crash
+    /// directory and workers don't all spend their time shrinking it.
+    fn save_failure(&self, target: &mut Target, shared: &Shared, bytes: &[u8], report: Report) {
+        if !shared.signatures.lock().unwrap().insert(report.signature()) {
+            return;
+        }
+        let path = match shared.crash_dir.save(bytes, &report) {
+            Ok(path) => path,
+            Err(e) => {
+                println!("could not save failing input: {}", e);
+                return;
+            }
+        };
+        if let Some(ref message) = report.panic_message {
+            println!("target panicked: {}", message);
+        }
+        println!(
+            "saved failing input to {} (seed {})",
+            path.display(),
+            report.seed
+        );
+        if !report.values.is_empty() {
+            println!("failing values: {}", report.values.join(", "));
+        }
+        if self.max_shrink_attempts == 0 {
+            return;
+        }
+
+        // NOTE -- a stopped run keeps the best shrink found so far
+        let shrunk = shrink(bytes, self.max_shrink_attempts, |candidate| {
+            if !shared.running() {
+                return false;
+            }
+            match target.exec(candidate) {
+                Ok(outcome) => outcome.failure() == Some(report.failure),
+                Err(e @ TargetError::Handshake(_)) | Err(e @ TargetError::Startup(_)) => {
+                    println!("{}", e);
+                    shared.stop(1);
+                    false
                 }
+                Err(_) => false,
             }
-        } // end loop
-        shm_unlink(self.shm_path.as_str()).expect("failed to unlink opened shm");
-        ::std::process::exit(exit_status.unwrap());
+        });
+        match shared.crash_dir.save_shrunk(&path, &shrunk) {
+            Ok(min_path) => println!(
+                "shrunk failing input from {} to {} bytes, saved to {}",
+                bytes.len(),
+                shrunk.len(),
+                min_path.display()
+            ),
+            Err(e) => println!("could not save shrunk input: {}", e),
+        }
     }
+
+    /// Run exactly `bytes` through `target` once and report what happened,
+    /// along with the values the test generated should it have failed
+    pub fn replay(&self, target: &Path, bytes: &[u8]) -> (Outcome, Vec<String>) {
+        assert!(bytes.len() < self.shm_total_bytes);
+        self.check_property(target);
+
+        let mut target = self.target(target, 0);
+        match target.exec(bytes) {
+            Ok(outcome) => {
+                let values = match outcome.failure() {
+                    Some(Failure::Failed) | Some(Failure::Panicked) => target.values(),
+                    _ => Vec::new(),
+                };
+                (outcome, values)
+            }
+            Err(e) => {
+                println!("{}", e);
+                // exiting skips the drop that unlinks the shared memory
+                drop(target);
+                ::std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// An id for this run, the process id and a random suffix
+fn run_id() -> String {
+    format!("{}-{:08x}", ::std::process::id(), rand::random::<u32>())
+}
+
+fn host_triple() -> String {
+    let rustc = ::std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc)
+        .arg("-vV")
+        .output()
+        .expect("could not run rustc to find the host triple");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.starts_with("host: "))
+        .map(|line| line["host: ".len()..].to_string())
+        .expect("rustc did not report a host triple")
 }
@@ -0,0 +1,326 @@
+use rand::Rng;
+use std::cmp;
+use std::str::FromStr;
+
+/// A single way of deriving a new input from an existing one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mutation {
+    /// Flip one bit
+    BitFlip,
+    /// Invert every bit of one byte
+    ByteFlip,
+    /// Overwrite an 8, 16, 32 or 64 bit integer with 0, 1, -1, MAX or MIN
+    InterestingValue,
+    /// Add or subtract a small amount from an 8, 16, 32 or 64 bit integer
+    Arithmetic,
+    /// Insert a block of random bytes
+    InsertBlock,
+    /// Remove a block of bytes
+    DeleteBlock,
+    /// Copy a block of bytes to another position
+    DuplicateBlock,
+    /// Join the front of the input to the back of another corpus entry
+    Splice,
+}
+
+/// Every [`Mutation`], the default set for a [`Mutator`]
+pub const ALL_MUTATIONS: [Mutation; 8] = [
+    Mutation::BitFlip,
+    Mutation::ByteFlip,
+    Mutation::InterestingValue,
+    Mutation::Arithmetic,
+    Mutation::InsertBlock,
+    Mutation::DeleteBlock,
+    Mutation::DuplicateBlock,
+    Mutation::Splice,
+];
+
+impl FromStr for Mutation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mutation, String> {
+        match s {
+            "bit-flip" => Ok(Mutation::BitFlip),
+            "byte-flip" => Ok(Mutation::ByteFlip),
+            "interesting-value" => Ok(Mutation::InterestingValue),
+            "arithmetic" => Ok(Mutation::Arithmetic),
+            "insert-block" => Ok(Mutation::InsertBlock),
+            "delete-block" => Ok(Mutation::DeleteBlock),
+            "duplicate-block" => Ok(Mutation::DuplicateBlock),
+            "splice" => Ok(Mutation::Splice),
+            _ => Err(format!("unknown mutation: {}", s)),
+        }
+    }
+}
+
+const MAX_BLOCK: usize = 32;
+const MAX_ARITH: u64 = 35;
+const WIDTHS: [usize; 4] = [1, 2, 4, 8];
+
+/// Derives new inputs from corpus entries by stacking randomly chosen
+/// [`Mutation`]s
+///
+/// Inputs are never grown past `max_len` bytes.
+#[derive(Debug, Clone)]
+pub struct Mutator {
+    mutations: Vec<Mutation>,
+    max_len: usize,
+    max_stack: usize,
+}
+
+impl Mutator {
+    /// Create a new Mutator using every mutation
+    pub fn new(max_len: usize) -> Self {
+        Mutator {
+            mutations: ALL_MUTATIONS.to_vec(),
+            max_len,
+            max_stack: 8,
+        }
+    }
+
+    /// Restrict the mutations that will be applied
+    pub fn mutations(mut self, mutations: &[Mutation]) -> Self {
+        assert!(!mutations.is_empty());
+        self.mutations = mutations.to_vec();
+        self
+    }
+
+    /// Set the non-default maximum number of mutations stacked per input
+    pub fn max_stack(mut self, max_stack: usize) -> Self {
+        assert!(max_stack > 0);
+        self.max_stack = max_stack;
+        self
+    }
+
+    /// Apply between one and `max_stack` mutations to `bytes`, drawing on
+    /// `other` for splicing
+    pub fn mutate<R: Rng>(&self, rng: &mut R, bytes: &mut Vec<u8>, other: Option<&[u8]>) {
+        for _ in 0..rng.gen_range(1, self.max_stack + 1) {
+            let mutation = self.mutations[rng.gen_range(0, self.mutations.len())];
+            self.apply(mutation, rng, bytes, other);
+        }
+    }
+
+    /// Apply `mutation` to `bytes`, returning false if it could not be
+    /// applied, say because there was no room to insert into
+    pub fn apply<R: Rng>(
+        &self,
+        mutation: Mutation,
+        rng: &mut R,
+        bytes: &mut Vec<u8>,
+        other: Option<&[u8]>,
+    ) -> bool {
+        if bytes.is_empty() {
+            return false;
+        }
+        match mutation {
+            Mutation::BitFlip => {
+                let idx = rng.gen_range(0, bytes.len());
+                bytes[idx] ^= 1 << rng.gen_range(0, 8);
+            }
+            Mutation::ByteFlip => {
+                let idx = rng.gen_range(0, bytes.len());
+                bytes[idx] ^= 0xff;
+            }
+            Mutation::InterestingValue => {
+                let width = match choose_width(rng, bytes.len()) {
+                    Some(width) => width,
+                    None => return false,
+                };
+                let bits = width * 8;
+                let value: u64 = match rng.gen_range(0, 5) {
+                    0 => 0,
+                    1 => 1,
+                    2 => u64::MAX,                // -1, MAX unsigned
+                    3 => u64::MAX >> (65 - bits), // MAX signed
+                    _ => 1 << (bits - 1),         // MIN signed
+                };
+                let idx = rng.gen_range(0, bytes.len() - width + 1);
+                write_le(&mut bytes[idx..idx + width], value);
+            }
+            Mutation::Arithmetic => {
+                let width = match choose_width(rng, bytes.len()) {
+                    Some(width) => width,
+                    None => return false,
+                };
+                let idx = rng.gen_range(0, bytes.len() - width + 1);
+                let delta = rng.gen_range(1, MAX_ARITH + 1);
+                let value = read_le(&bytes[idx..idx + width]);
+                let value = if rng.gen() {
+                    value.wrapping_add(delta)
+                } else {
+                    value.wrapping_sub(delta)
+                };
+                write_le(&mut bytes[idx..idx + width], value);
+            }
+            Mutation::InsertBlock => {
+                let room = self.max_len.saturating_sub(bytes.len());
+                if room == 0 {
+                    return false;
+                }
+                let len = rng.gen_range(1, cmp::min(room, MAX_BLOCK) + 1);
+                let idx = rng.gen_range(0, bytes.len() + 1);
+                let block: Vec<u8> = (0..len).map(|_| rng.gen::<u8>()).collect();
+                bytes.splice(idx..idx, block);
+            }
+            Mutation::DeleteBlock => {
+                let len = rng.gen_range(1, cmp::min(bytes.len(), MAX_BLOCK) + 1);
+                let idx = rng.gen_range(0, bytes.len() - len + 1);
+                bytes.drain(idx..idx + len);
+            }
+            Mutation::DuplicateBlock => {
+                let room = self.max_len.saturating_sub(bytes.len());
+                if room == 0 {
+                    return false;
+                }
+                let len = rng.gen_range(1, cmp::min(cmp::min(room, bytes.len()), MAX_BLOCK) + 1);
+                let from = rng.gen_range(0, bytes.len() - len + 1);
+                let to = rng.gen_range(0, bytes.len() + 1);
+                let block: Vec<u8> = bytes[from..from + len].to_vec();
+                bytes.splice(to..to, block);
+            }
+            Mutation::Splice => {
+                let other = match other {
+                    Some(other) if !other.is_empty() => other,
+                    _ => return false,
+                };
+                let front = rng.gen_range(1, bytes.len() + 1);
+                let back = rng.gen_range(0, other.len());
+                bytes.truncate(front);
+                bytes.extend_from_slice(&other[back..]);
+                bytes.truncate(self.max_len);
+            }
+        }
+        true
+    }
+}
+
+fn choose_width<R: Rng>(rng: &mut R, len: usize) -> Option<usize> {
+    let fits = WIDTHS.iter().take_while(|w| **w <= len).count();
+    if fits == 0 {
+        None
+    } else {
+        Some(WIDTHS[rng.gen_range(0, fits)])
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |acc, b| (acc << 8) | u64::from(*b))
+}
+
+fn write_le(bytes: &mut [u8], value: u64) {
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (value >> (8 * i)) as u8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    fn rng() -> SmallRng {
+        SmallRng::seed_from_u64(0xdead_beef)
+    }
+
+    #[test]
+    fn mutation_from_str() {
+        assert_eq!("bit-flip".parse(), Ok(Mutation::BitFlip));
+        assert_eq!("splice".parse(), Ok(Mutation::Splice));
+        assert!("bogus".parse::<Mutation>().is_err());
+    }
+
+    #[test]
+    fn mutator_bit_flip() {
+        let mut rng = rng();
+        let mutator = Mutator::new(16);
+        for _ in 0..100 {
+            let mut bytes = vec![0; 8];
+            assert!(mutator.apply(Mutation::BitFlip, &mut rng, &mut bytes, None));
+            let ones: u32 = bytes.iter().map(|b| b.count_ones()).sum();
+            assert_eq!(ones, 1);
+        }
+    }
+
+    #[test]
+    fn mutator_interesting_value() {
+        let mut rng = rng();
+        let mutator = Mutator::new(1);
+        for _ in 0..100 {
+            let mut bytes = vec![0x42];
+            assert!(mutator.apply(Mutation::InterestingValue, &mut rng, &mut bytes, None));
+            assert!([0x00, 0x01, 0xff, 0x7f, 0x80].contains(&bytes[0]));
+        }
+    }
+
+    #[test]
+    fn mutator_arithmetic() {
+        let mut rng = rng();
+        let mutator = Mutator::new(1);
+        for _ in 0..100 {
+            let mut bytes = vec![100];
+            assert!(mutator.apply(Mutation::Arithmetic, &mut rng, &mut bytes, None));
+            let delta = u64::from((i16::from(bytes[0]) - 100).unsigned_abs());
+            assert!((1..=MAX_ARITH).contains(&delta));
+        }
+    }
+
+    #[test]
+    fn mutator_blocks_respect_bounds() {
+        let mut rng = rng();
+        let mutator = Mutator::new(8);
+
+        let mut bytes = vec![1; 8];
+        assert!(!mutator.apply(Mutation::InsertBlock, &mut rng, &mut bytes, None));
+        assert!(!mutator.apply(Mutation::DuplicateBlock, &mut rng, &mut bytes, None));
+
+        let mut bytes = vec![1];
+        assert!(mutator.apply(Mutation::DeleteBlock, &mut rng, &mut bytes, None));
+        assert!(bytes.is_empty());
+        assert!(!mutator.apply(Mutation::DeleteBlock, &mut rng, &mut bytes, None));
+
+        for _ in 0..100 {
+            let mut bytes = vec![1, 2, 3, 4];
+            assert!(mutator.apply(Mutation::InsertBlock, &mut rng, &mut bytes, None));
+            assert!(bytes.len() > 4 && bytes.len() <= 8);
+            let len = bytes.len();
+            assert!(mutator.apply(Mutation::DeleteBlock, &mut rng, &mut bytes, None));
+            assert!(bytes.len() < len);
+        }
+    }
+
+    #[test]
+    fn mutator_splice() {
+        let mut rng = rng();
+        let mutator = Mutator::new(8);
+        let mut bytes = vec![1; 4];
+        assert!(!mutator.apply(Mutation::Splice, &mut rng, &mut bytes, None));
+        for _ in 0..100 {
+            let mut bytes = vec![1; 4];
+            assert!(mutator.apply(Mutation::Splice, &mut rng, &mut bytes, Some(&[2; 8])));
+            assert_eq!(bytes[0], 1);
+            assert!(bytes.len() <= 8);
+            let split = bytes.iter().position(|b| *b == 2).unwrap_or(bytes.len());
+            assert!(bytes[..split].iter().all(|b| *b == 1));
+            assert!(bytes[split..].iter().all(|b| *b == 2));
+        }
+    }
+
+    #[test]
+    fn mutator_mutate_only_uses_chosen_mutations() {
+        let mut rng = rng();
+        let mutator = Mutator::new(64)
+            .mutations(&[Mutation::ByteFlip])
+            .max_stack(1);
+        for _ in 0..100 {
+            let mut bytes = vec![0; 4];
+            mutator.mutate(&mut rng, &mut bytes, None);
+            assert_eq!(bytes.len(), 4);
+            assert_eq!(bytes.iter().filter(|b| **b == 0xff).count(), 1);
+        }
+    }
+}
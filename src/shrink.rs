@@ -0,0 +1,140 @@
+/// Shrink a failing input
+///
+/// Candidates are derived from `bytes` by truncation, zeroing chunks and
+/// halving individual byte values. A candidate is kept only if `still_fails`
+/// holds for it, and passes repeat until no further progress is made or
+/// `max_attempts` candidates have been tried. The smallest failing input seen
+/// is returned.
+pub fn shrink<F>(bytes: &[u8], max_attempts: usize, still_fails: F) -> Vec<u8>
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    let mut shrinker = Shrinker {
+        best: bytes.to_vec(),
+        attempts: 0,
+        max_attempts,
+        still_fails,
+    };
+    loop {
+        let mut progress = shrinker.truncate();
+        progress |= shrinker.zero_chunks();
+        progress |= shrinker.halve_values();
+        if !progress || shrinker.exhausted() {
+            break;
+        }
+    }
+    shrinker.best
+}
+
+struct Shrinker<F> {
+    best: Vec<u8>,
+    attempts: usize,
+    max_attempts: usize,
+    still_fails: F,
+}
+
+impl<F> Shrinker<F>
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    fn exhausted(&self) -> bool {
+        self.attempts >= self.max_attempts
+    }
+
+    fn attempt(&mut self, candidate: Vec<u8>) -> bool {
+        if self.exhausted() {
+            return false;
+        }
+        self.attempts += 1;
+        if (self.still_fails)(&candidate) {
+            self.best = candidate;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn truncate(&mut self) -> bool {
+        let mut progress = false;
+        let mut chunk = self.best.len();
+        while chunk > 0 && !self.exhausted() {
+            let len = self.best.len();
+            if len >= chunk && self.attempt(self.best[..len - chunk].to_vec()) {
+                progress = true;
+            } else {
+                chunk /= 2;
+            }
+        }
+        progress
+    }
+
+    fn zero_chunks(&mut self) -> bool {
+        let mut progress = false;
+        let mut chunk = ::std::cmp::max(self.best.len() / 2, 1);
+        loop {
+            let mut start = 0;
+            while start < self.best.len() && !self.exhausted() {
+                let end = ::std::cmp::min(start + chunk, self.best.len());
+                if self.best[start..end].iter().any(|b| *b != 0) {
+                    let mut candidate = self.best.clone();
+                    for b in candidate[start..end].iter_mut() {
+                        *b = 0;
+                    }
+                    progress |= self.attempt(candidate);
+                }
+                start = end;
+            }
+            if chunk == 1 || self.exhausted() {
+                break;
+            }
+            chunk /= 2;
+        }
+        progress
+    }
+
+    fn halve_values(&mut self) -> bool {
+        let mut progress = false;
+        for idx in 0..self.best.len() {
+            while self.best[idx] > 0 && !self.exhausted() {
+                let mut candidate = self.best.clone();
+                candidate[idx] /= 2;
+                if self.attempt(candidate) {
+                    progress = true;
+                } else {
+                    break;
+                }
+            }
+        }
+        progress
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shrink_to_minimal() {
+        let bytes = [5, 7, 200, 9, 9];
+        let shrunk = shrink(&bytes, 1_000, |b| b.len() >= 3 && b[2] >= 10);
+        assert_eq!(shrunk, vec![0, 0, 12]);
+    }
+
+    #[test]
+    fn shrink_to_empty() {
+        let shrunk = shrink(&[1, 2, 3], 1_000, |_| true);
+        assert_eq!(shrunk, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn shrink_respects_attempt_limit() {
+        let bytes = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut calls = 0;
+        let shrunk = shrink(&bytes, 3, |_| {
+            calls += 1;
+            false
+        });
+        assert_eq!(calls, 3);
+        assert_eq!(shrunk, bytes.to_vec());
+    }
+}
@@ -0,0 +1,37 @@
+use crate::crash::{CrashKind, ALL_CRASH_KINDS};
+use std::fmt;
+
+/// Running totals of what the target workers have seen
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub test_cases: u64,
+    pub restarts: u64,
+    pub passed: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub panicked: u64,
+    pub insufficient_bytes: u64,
+    pub crash_failure: u64,
+    pub signaled: [u64; ALL_CRASH_KINDS.len()],
+    pub hangs: u64,
+}
+
+impl Stats {
+    pub fn signal(&mut self, kind: CrashKind) {
+        self.signaled[kind as usize] += 1;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let signals: Vec<String> = ALL_CRASH_KINDS
+            .iter()
+            .map(|kind| format!("{}: {}", kind.name(), self.signaled[*kind as usize]))
+            .collect();
+        write!(
+            f,
+            "TestCases: {} Restarts: {} Passed: {} Skipped: {} Failed: {} Panicked: {} InsufficientBytes: {} CrashFail: {} Signaled: [{}] Hangs: {}",
+            self.test_cases, self.restarts, self.passed, self.skipped, self.failed, self.panicked, self.insufficient_bytes, self.crash_failure, signals.join(" "), self.hangs
+        )
+    }
+}
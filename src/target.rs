@@ -0,0 +1,459 @@
+use crate::crash::Failure;
+use nix::fcntl::OFlag;
+use nix::sys::mman::{mmap, shm_open, shm_unlink, MapFlags, ProtFlags};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::stat::Mode;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{execve, fork, ftruncate, getpid, getppid, setpgid, ForkResult, Pid};
+use rqc_core::{
+    ClientStatus, Comm, CommError, ServerStatus, TestStatus, Waiter, CAP_PROPERTIES,
+    LIST_PROPERTIES_ENV, SHM_PATH_ENV,
+};
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, error, fmt, ptr, thread};
+
+/// The longest the server sleeps on the target's status before checking that
+/// the target is still alive
+const CHILD_POLL: Duration = Duration::from_millis(5);
+
+/// The time a target is given to start up and be ready for an input, apart
+/// from the timeout of the input itself
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The time a binary is given to list its properties
+const LIST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The result of running a single input against a target
+#[derive(Debug)]
+pub enum Outcome {
+    /// The target ran the test and reported back
+    Test(TestStatus),
+    /// The test panicked with the given message
+    Panicked(String),
+    /// The target exited with the given status before reporting
+    Exited(i32),
+    /// The target was terminated by a signal before reporting
+    Signaled(Signal),
+    /// The target did not report within the timeout and was killed
+    TimedOut,
+}
+
+impl Outcome {
+    /// The failure this outcome represents, if any
+    pub fn failure(&self) -> Option<Failure> {
+        match *self {
+            Outcome::Test(TestStatus::Failed) => Some(Failure::Failed),
+            Outcome::Test(_) | Outcome::Exited(0) => None,
+            Outcome::Exited(status) => Some(Failure::Exited(status)),
+            Outcome::Signaled(signal) => Some(Failure::Signaled(signal)),
+            Outcome::TimedOut => Some(Failure::TimedOut),
+            Outcome::Panicked(_) => Some(Failure::Panicked),
+        }
+    }
+}
+
+/// Why a target could not be run at all
+#[derive(Debug)]
+pub enum TargetError {
+    /// Waiting on the target process failed
+    Wait(nix::Error),
+    /// The target could not complete the handshake, so no input it was
+    /// given can be blamed for what it did
+    Handshake(String),
+    /// The target was not ready for an input within `STARTUP_TIMEOUT`
+    Startup(String),
+    /// The target left the shared memory in a state it can't be in
+    Comm(CommError),
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TargetError::Wait(ref e) => write!(f, "waiting on target failed with: {}", e),
+            TargetError::Handshake(ref problem) => {
+                write!(f, "target handshake failed: {}", problem)
+            }
+            TargetError::Startup(ref problem) => write!(f, "target did not start: {}", problem),
+            TargetError::Comm(ref e) => write!(f, "target broke the protocol: {}", e),
+        }
+    }
+}
+
+impl error::Error for TargetError {}
+
+/// A test target process and the shared memory used to talk to it
+///
+/// The target is started lazily and restarted whenever it dies, so callers
+/// only ever need to hand it inputs.
+pub struct Target {
+    comm: Comm,
+    c_path: CString,
+    /// The arguments the target is started with, the shm path following the
+    /// program for targets that read it from argv
+    c_args: Vec<CString>,
+    /// The environment the target is started with, naming its shared memory
+    c_env: Vec<CString>,
+    shm_path: String,
+    child: Option<Pid>,
+    timeout: Duration,
+    /// Whether the running target has completed the handshake
+    negotiated: bool,
+}
+
+impl Target {
+    pub fn new(target: &Path, shm_path: &str, shm_total_bytes: usize) -> Target {
+        let def_file_mode = Mode::S_IRUSR
+            | Mode::S_IWUSR
+            | Mode::S_IRGRP
+            | Mode::S_IWGRP
+            | Mode::S_IROTH
+            | Mode::S_IWOTH;
+        let flags = OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_RDWR;
+        let memfd = match shm_open(shm_path, flags, def_file_mode) {
+            Ok(memfd) => memfd,
+            Err(e) => {
+                println!("could not create shared memory {}: {}", shm_path, e);
+                ::std::process::exit(1);
+            }
+        };
+        if let Err(e) = ftruncate(memfd, shm_total_bytes as i64) {
+            shm_unlink(shm_path).expect("failed to unlink opened shm");
+            println!(
+                "could not truncate shared memory to appropriate size: {}",
+                e
+            );
+            ::std::process::exit(1);
+        }
+        let ptr: *mut libc::c_void = unsafe {
+            mmap(
+                ptr::null_mut(),
+                shm_total_bytes,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                memfd,
+                0,
+            )
+            .expect("could not memory map shared memory file")
+        };
+        let comm = match Comm::create(ptr, shm_total_bytes) {
+            Ok(comm) => comm,
+            Err(e) => {
+                let _ = shm_unlink(shm_path);
+                println!("could not set up shared memory: {}", e);
+                ::std::process::exit(1);
+            }
+        };
+
+        let c_path = CString::new(
+            target
+                .as_os_str()
+                .to_str()
+                .expect("path contains invalid unicode characters"),
+        )
+        .expect("unable to coerce path into c-style string");
+        let c_args = vec![
+            c_path.clone(),
+            CString::new(shm_path).expect("unable to coerce shm path into c-style string"),
+        ];
+        let mut c_env: Vec<CString> = env::vars_os()
+            .filter(|(key, _)| key != SHM_PATH_ENV)
+            .filter_map(|(key, value)| {
+                let mut var = key.into_vec();
+                var.push(b'=');
+                var.extend(value.into_vec());
+                CString::new(var).ok()
+            })
+            .collect();
+        c_env.push(
+            CString::new(format!("{}={}", SHM_PATH_ENV, shm_path))
+                .expect("unable to coerce shm path into c-style string"),
+        );
+
+        Target {
+            comm,
+            c_path,
+            c_args,
+            c_env,
+            shm_path: shm_path.to_string(),
+            child: None,
+            timeout: Duration::from_secs(1),
+            negotiated: false,
+        }
+    }
+
+    /// Set the non-default time a target is given to run one input
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run the property named `name` rather than the target's first
+    pub fn property(mut self, name: &str) -> Self {
+        self.comm
+            .server_property(name)
+            .expect("property name does not fit shared memory");
+        self
+    }
+
+    fn kill(&mut self) {
+        if let Some(child) = self.child.take() {
+            let _ = kill(child, Signal::SIGKILL);
+            let _ = waitpid(child, None);
+        }
+    }
+
+    /// Check the hello of a newly started target, killing it if the two
+    /// cannot talk
+    fn handshake(&mut self) -> Result<(), TargetError> {
+        let property = self.comm.property();
+        let problem = match self.comm.negotiate() {
+            Err(e) => format!(
+                "{}; rebuild the target against this rqc-core with `cargo rqc build`",
+                e
+            ),
+            Ok(_) if !property.is_empty() && !self.comm.supports(CAP_PROPERTIES) => format!(
+                "the target cannot run property {}, it only runs its first",
+                property
+            ),
+            Ok(_) => {
+                self.negotiated = true;
+                return Ok(());
+            }
+        };
+        self.kill();
+        Err(TargetError::Handshake(problem))
+    }
+
+    /// The error for a target that stopped, having been `what`, without ever
+    /// saying hello
+    ///
+    /// A target built against an older rqc-core never does, nor does one
+    /// that fails before it reaches the handshake.
+    fn never_negotiated(&mut self, what: &str) -> TargetError {
+        self.kill();
+        TargetError::Handshake(format!(
+            "target {} before completing the handshake; if it was built against an older rqc-core rebuild it with `cargo rqc build`",
+            what
+        ))
+    }
+
+    /// The client's status, killing a target that has written nonsense
+    fn status(&mut self) -> Result<ClientStatus, TargetError> {
+        self.comm.client_status().map_err(|e| {
+            self.kill();
+            TargetError::Comm(e)
+        })
+    }
+
+    fn spawn(&mut self) -> Pid {
+        // a dead target may leave stale status words behind, clear them so the
+        // next target starts fresh
+        self.comm.client_reset();
+        self.comm.server_reset();
+        self.comm.handshake_reset();
+        self.negotiated = false;
+        let server = getpid();
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => child,
+            Ok(ForkResult::Child) => {
+                // NOTE -- the target gets its own process group so that
+                // Ctrl-C reaches only the server, which stops it. Should the
+                // server die instead the target is killed with it. Strictly
+                // that happens when the forking worker thread exits, but a
+                // worker never outlives its target anyway.
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                unsafe {
+                    libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+                }
+                if getppid() != server {
+                    ::std::process::exit(1);
+                }
+                let Err(e) = execve(&self.c_path, &self.c_args, &self.c_env);
+                println!("could not execve: {}", e);
+                ::std::process::exit(1);
+            }
+            Err(_) => {
+                println!("Unable to fork target");
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    /// Run `bytes` through the target, starting it first if it is not
+    /// already running
+    ///
+    /// If the target does not report within the timeout it is killed, to be
+    /// restarted by the next call. A target that dies or hangs before the
+    /// handshake never saw `bytes`, so that is an error rather than an
+    /// `Outcome`.
+    pub fn exec(&mut self, bytes: &[u8]) -> Result<Outcome, TargetError> {
+        let child = match self.child {
+            Some(child) => child,
+            None => {
+                let child = self.spawn();
+                self.child = Some(child);
+                child
+            }
+        };
+
+        // the timeout is the input's, so until the input is sent the target
+        // is only held to STARTUP_TIMEOUT
+        let mut deadline = Instant::now() + STARTUP_TIMEOUT;
+        let mut sent = false;
+        let mut waiter = Waiter::default();
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                self.kill();
+                if !sent {
+                    let problem = if self.negotiated {
+                        "it was not ready for an input"
+                    } else {
+                        "it did not complete the handshake"
+                    };
+                    return Err(TargetError::Startup(format!(
+                        "{} within {:?}",
+                        problem, STARTUP_TIMEOUT
+                    )));
+                }
+                return Ok(Outcome::TimedOut);
+            }
+            // NOTE -- a target that dies never wakes us, so sleep no
+            // longer than CHILD_POLL between checks on it
+            let timeout = Some(::std::cmp::min(deadline - now, CHILD_POLL));
+            match waitpid(child, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) => match self.status()? {
+                    ClientStatus::Ready if !self.negotiated => self.handshake()?,
+                    ClientStatus::Ready => {
+                        match self.comm.server_status().map_err(TargetError::Comm)? {
+                            ServerStatus::Default if !sent => {
+                                waiter.reset();
+                                self.comm.coverage_reset();
+                                let _ = self
+                                    .comm
+                                    .write(bytes)
+                                    .expect("unable to write bytes to target");
+                                sent = true;
+                                self.comm.server_ready();
+                                deadline = Instant::now() + self.timeout;
+                            }
+                            _ => self
+                                .comm
+                                .client_wait(&ClientStatus::Ready, &mut waiter, timeout),
+                        }
+                    }
+                    ClientStatus::Test(test_status) => {
+                        let outcome = match test_status {
+                            TestStatus::Panicked => Outcome::Panicked(self.comm.panic_message()),
+                            test_status => Outcome::Test(test_status),
+                        };
+                        self.comm.client_reset();
+                        self.comm.server_reset();
+                        return Ok(outcome);
+                    }
+                    seen => self.comm.client_wait(&seen, &mut waiter, timeout),
+                },
+                Ok(WaitStatus::Exited(_, status)) => {
+                    self.child = None;
+                    if !self.negotiated {
+                        // a target that speaks another protocol says hello
+                        // and exits
+                        if self.comm.client_version() != 0 {
+                            self.handshake()?;
+                        }
+                        return Err(
+                            self.never_negotiated(&format!("exited with status {}", status))
+                        );
+                    }
+                    return Ok(Outcome::Exited(status));
+                }
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    self.child = None;
+                    if !self.negotiated {
+                        return Err(self.never_negotiated(&format!("died with {:?}", signal)));
+                    }
+                    return Ok(Outcome::Signaled(signal));
+                }
+                Ok(_) => {
+                    let seen = self.status()?;
+                    self.comm.client_wait(&seen, &mut waiter, timeout)
+                }
+                Err(e) => {
+                    self.child = None;
+                    return Err(TargetError::Wait(e));
+                }
+            }
+        }
+    }
+}
+
+impl Target {
+    /// The edge counters recorded during the last `exec`
+    pub fn coverage(&self) -> &[u8] {
+        self.comm.coverage()
+    }
+
+    /// The values the test generated, valid only when the last `exec` failed
+    /// or panicked
+    pub fn values(&self) -> Vec<String> {
+        self.comm.values()
+    }
+}
+
+/// The names of the properties `target` registers, in registration order
+///
+/// `cargo rqc build` asks this of binaries that may not be targets at all, so
+/// one that doesn't answer within `LIST_TIMEOUT` is killed along with
+/// anything it started.
+pub fn list_properties(target: &Path) -> io::Result<Vec<String>> {
+    let mut child = Command::new(target)
+        .env_remove(SHM_PATH_ENV)
+        .env(LIST_PROPERTIES_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("child stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut listed = Vec::new();
+        stdout.read_to_end(&mut listed).map(|_| listed)
+    });
+    let deadline = Instant::now() + LIST_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // whatever the binary started goes with it
+            let _ = kill(Pid::from_raw(-(child.id() as i32)), Signal::SIGKILL);
+            let _ = child.wait();
+            return Err(io::Error::other(format!(
+                "target did not list its properties within {:?}",
+                LIST_TIMEOUT
+            )));
+        }
+        thread::sleep(CHILD_POLL);
+    };
+    if !status.success() {
+        return Err(io::Error::other(format!("target exited with {}", status)));
+    }
+    let listed = reader.join().expect("stdout reader panicked")?;
+    Ok(String::from_utf8_lossy(&listed)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        self.kill();
+        let _ = shm_unlink(self.shm_path.as_str());
+    }
+}